@@ -0,0 +1,96 @@
+use std::io::Read;
+
+use url::Url;
+
+use crate::{
+    time::parse_rfc3339,
+    visit::{Visit, VisitType},
+};
+
+/// An entry of a Google My Activity `MyActivity.json`, as found in the `My Activity/Search` folder of a takeout.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityEntry {
+    pub title: String,
+    /// Missing for activity which didn't happen on a page, e.g. voice searches.
+    #[serde(default)]
+    pub title_url: Option<Url>,
+    /// RFC 3339 timestamp, e.g. `2025-01-25T10:13:20.123Z`.
+    pub time: String,
+}
+
+/// Whether `url` is a search results page, a `/search` page with a `q` parameter like Google's and Bing's.
+pub fn is_search_results(url: &Url) -> bool {
+    url.path() == "/search" && url.query_pairs().any(|(key, _)| key == "q")
+}
+
+/// Reads the searches from a Google My Activity `MyActivity.json`.
+///
+/// Every entry whose URL is a `/search` page with a `q` parameter becomes a typed visit to the search results,
+/// titled with the search terms. Other activity, like visited results, is skipped,
+/// as the browser history already has those.
+pub fn read_searches(reader: impl Read) -> anyhow::Result<Vec<Visit>> {
+    let activity: Vec<ActivityEntry> = serde_json::from_reader(reader)?;
+    let mut searches = Vec::new();
+    for entry in activity {
+        let Some(url) = entry.title_url else {
+            continue;
+        };
+        if !is_search_results(&url) {
+            continue;
+        }
+        let Some((_, terms)) = url.query_pairs().find(|(key, _)| key == "q") else {
+            continue;
+        };
+        let time = parse_rfc3339(&entry.time).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid time {:?} of activity {:?}.",
+                entry.time,
+                entry.title
+            )
+        })?;
+        searches.push(Visit {
+            title: Some(terms.into_owned()),
+            visit_type: VisitType::Typed,
+            ..Visit::new(url, time)
+        });
+    }
+    Ok(searches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_searches;
+    use crate::visit::VisitType;
+
+    #[test]
+    fn test_read_searches() {
+        let input = r#"[
+            {
+                "header": "Search",
+                "title": "Searched for rust borrow checker",
+                "titleUrl": "https://www.google.com/search?q=rust+borrow+checker",
+                "time": "2025-01-25T10:13:20.123Z",
+                "products": ["Search"]
+            },
+            {
+                "header": "Search",
+                "title": "Visited The Rust Programming Language",
+                "titleUrl": "https://www.google.com/url?q=https://doc.rust-lang.org/book/&usg=abc",
+                "time": "2025-01-25T10:13:30.000Z",
+                "products": ["Search"]
+            },
+            {
+                "header": "Search",
+                "title": "Searched for nixos",
+                "time": "2025-01-25T10:14:00.000Z",
+                "products": ["Search"]
+            }
+        ]"#;
+        let searches = read_searches(input.as_bytes()).unwrap();
+        assert_eq!(searches.len(), 1);
+        assert_eq!(searches[0].title.as_deref(), Some("rust borrow checker"));
+        assert_eq!(searches[0].time, 1737800000123000);
+        assert_eq!(searches[0].visit_type, VisitType::Typed);
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::{Read, Write};
+
+use anyhow::Context;
+
+use crate::visit::Visit;
+
+const HEADER: [&str; 3] = ["url", "title", "visit_date"];
+
+/// Reads history from a CSV file with the columns `url`, `title` and `visit_date`.
+///
+/// `visit_date` is in microseconds since the unix epoch, like Firefox stores it.
+/// A header row is optional.
+pub fn read_history(mut reader: impl Read) -> anyhow::Result<Vec<Visit>> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    parse_history(&input)
+}
+
+/// Parses history in the format of [`read_history`] from a string.
+pub fn parse_history(input: &str) -> anyhow::Result<Vec<Visit>> {
+    let mut entries = Vec::new();
+    for (index, record) in parse_records(input)?.into_iter().enumerate() {
+        if index == 0 && record == HEADER {
+            continue;
+        }
+        let [url, title, visit_date] = <[String; 3]>::try_from(record).map_err(|record| {
+            anyhow::anyhow!(
+                "CSV record {} has {} fields, expected 3.",
+                index + 1,
+                record.len()
+            )
+        })?;
+        let url = url
+            .parse()
+            .with_context(|| format!("CSV record {} has an invalid url.", index + 1))?;
+        let time = visit_date
+            .parse()
+            .with_context(|| format!("CSV record {} has an invalid visit_date.", index + 1))?;
+        entries.push(Visit {
+            title: if title.is_empty() { None } else { Some(title) },
+            ..Visit::new(url, time)
+        });
+    }
+    Ok(entries)
+}
+
+/// Writes the history as CSV in the format understood by [`read_history`].
+pub fn write_history(mut writer: impl Write, entries: &[Visit]) -> std::io::Result<()> {
+    writeln!(writer, "{}", HEADER.join(","))?;
+    for entry in entries {
+        writeln!(
+            writer,
+            "{},{},{}",
+            escape_field(entry.url.as_str()),
+            escape_field(entry.title.as_deref().unwrap_or_default()),
+            entry.time
+        )?;
+    }
+    Ok(())
+}
+
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// See: https://www.rfc-editor.org/rfc/rfc4180
+fn parse_records(input: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        anyhow::bail!("CSV input ends inside a quoted field.");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_history, write_history};
+    use crate::visit::Visit;
+
+    #[test]
+    fn test_round_trip() {
+        let entries = [
+            Visit {
+                title: Some("Hello, \"World\"\nSecond line".to_string()),
+                ..Visit::new("https://example.com/a,b".parse().unwrap(), 1737800000123456)
+            },
+            Visit::new(
+                "https://www.mozilla.org/".parse().unwrap(),
+                1737600000000000,
+            ),
+        ];
+        let mut output = Vec::new();
+        write_history(&mut output, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "url,title,visit_date\n\"https://example.com/a,b\",\"Hello, \"\"World\"\"\nSecond line\",1737800000123456\nhttps://www.mozilla.org/,,1737600000000000\n"
+        );
+
+        let parsed = read_history(output.as_slice()).unwrap();
+        assert_eq!(*parsed, entries);
+    }
+
+    #[test]
+    fn test_read_without_header() {
+        let parsed =
+            read_history("https://example.com/,Example,1\r\nhttps://example.org/,,2".as_bytes())
+                .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].title.as_deref(), Some("Example"));
+        assert_eq!(parsed[1].title, None);
+        assert_eq!(parsed[1].url.as_str(), "https://example.org/");
+        assert_eq!(parsed[1].time, 2);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert!(read_history("https://example.com/,Example".as_bytes()).is_err());
+        assert!(read_history("https://example.com/,\"Example,1".as_bytes()).is_err());
+        assert!(read_history("not a url,Example,1".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,406 @@
+//! Compares visits against a database without writing to it, for `--dry-run`, `--verify-hashes` and `--dedup-against`,
+//! and two databases with each other, for the `diff` subcommand.
+
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::OptionalExtension;
+
+use crate::{hash, schema_map::SchemaMap, visit::Visit};
+
+/// How an import would change a single page.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PageDiff {
+    pub url: String,
+    /// Whether the page is already in the database.
+    pub exists: bool,
+    /// visit_count before the import.
+    pub visit_count: u32,
+    /// visit_count after the import.
+    pub new_visit_count: u32,
+    /// Visits which would be added.
+    pub new_visits: u32,
+    /// Visits which would be skipped because they already exist.
+    pub duplicate_visits: u32,
+}
+
+/// How an import would change the database, by page in the order of the input.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Diff {
+    pub pages: Vec<PageDiff>,
+}
+
+impl Diff {
+    pub fn new_pages(&self) -> usize {
+        self.pages.iter().filter(|page| !page.exists).count()
+    }
+
+    pub fn existing_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.exists).count()
+    }
+
+    pub fn new_visits(&self) -> u32 {
+        self.pages.iter().map(|page| page.new_visits).sum()
+    }
+
+    pub fn duplicate_visits(&self) -> u32 {
+        self.pages.iter().map(|page| page.duplicate_visits).sum()
+    }
+}
+
+/// Works out what importing `visits` would do, as if existing visits are skipped.
+///
+/// `delta` and `touch_existing_places` have the meaning of the same [`crate::places::ImportOptions`].
+/// Only reads from `connection`.
+pub fn diff(
+    connection: &rusqlite::Connection,
+    visits: &[Visit],
+    delta: bool,
+    touch_existing_places: bool,
+    schema: &SchemaMap,
+) -> anyhow::Result<Diff> {
+    let mut find_place = connection
+        .prepare(&schema.sql("SELECT id, IFNULL(visit_count, 0) FROM moz_places WHERE url = ?1"))?;
+    let mut time_exists = connection.prepare(
+        &schema.sql("SELECT EXISTS(SELECT 1 FROM moz_historyvisits WHERE visit_date = ?1)"),
+    )?;
+    let mut place_time_exists = connection.prepare(&schema.sql(
+        "SELECT EXISTS(SELECT 1 FROM moz_historyvisits WHERE place_id = ?1 AND visit_date = ?2)",
+    ))?;
+
+    let mut diff = Diff::default();
+    let mut pages: HashMap<&str, (usize, Option<u32>)> = HashMap::new();
+    // visits of the input count as existing for the ones after them, like they do when importing
+    let mut times = HashSet::new();
+    let mut url_times = HashSet::new();
+    for visit in visits {
+        let url = visit.url.as_str();
+        let (index, place) = match pages.get(url) {
+            Some(page) => *page,
+            None => {
+                let place: Option<(u32, u32)> = find_place
+                    .query_row([url], |row| Ok((row.get(0)?, row.get(1)?)))
+                    .optional()?;
+                diff.pages.push(PageDiff {
+                    url: url.to_string(),
+                    exists: place.is_some(),
+                    visit_count: place.map_or(0, |(_, count)| count),
+                    new_visit_count: place.map_or(0, |(_, count)| count),
+                    new_visits: 0,
+                    duplicate_visits: 0,
+                });
+                let page = (diff.pages.len() - 1, place.map(|(id, _)| id));
+                pages.insert(url, page);
+                page
+            }
+        };
+
+        let duplicate = if delta {
+            !url_times.insert((url, visit.time))
+                || match place {
+                    Some(place) => {
+                        place_time_exists.query_row((place, visit.time), |row| row.get(0))?
+                    }
+                    None => false,
+                }
+        } else {
+            !times.insert(visit.time) || time_exists.query_row([visit.time], |row| row.get(0))?
+        };
+
+        let page = &mut diff.pages[index];
+        if duplicate {
+            page.duplicate_visits += 1;
+            continue;
+        }
+        page.new_visits += 1;
+        if !page.exists || touch_existing_places {
+            page.new_visit_count += 1;
+        }
+    }
+    Ok(diff)
+}
+
+/// Which of `visits` already exist in another database, e.g. another profile, by their URL and time,
+/// like with `delta` of [`crate::places::ImportOptions`]. Only reads from `connection`.
+pub fn exists_in(
+    connection: &rusqlite::Connection,
+    visits: &[Visit],
+    schema: &SchemaMap,
+) -> anyhow::Result<Vec<bool>> {
+    let mut exists = connection.prepare(&schema.sql(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM moz_historyvisits
+                JOIN moz_places ON moz_places.id = moz_historyvisits.place_id
+                WHERE moz_places.url_hash = ?1 AND moz_places.url = ?2 AND visit_date = ?3
+            )
+        "#,
+    ))?;
+    visits
+        .iter()
+        .map(|visit| {
+            Ok(
+                exists.query_row((visit.url_hash()?, visit.url.as_str(), visit.time), |row| {
+                    row.get(0)
+                })?,
+            )
+        })
+        .collect()
+}
+
+/// A place whose stored url_hash differs from the one [`hash::hash`] computes for its URL.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct HashMismatch {
+    pub url: String,
+    /// `moz_places.url_hash` in the database.
+    pub stored: u64,
+    /// The hash computed for the URL.
+    pub computed: u64,
+}
+
+/// Checks the url_hash of the places of `visits` which already exist in the database against [`hash::hash`],
+/// each URL once and in the order of the input. Mismatches hint at a bug in the hash function,
+/// or a Firefox version which hashes differently. Only reads from `connection`.
+pub fn verify_hashes(
+    connection: &rusqlite::Connection,
+    visits: &[Visit],
+    schema: &SchemaMap,
+) -> anyhow::Result<Vec<HashMismatch>> {
+    let mut find_hash =
+        connection.prepare(&schema.sql("SELECT url_hash FROM moz_places WHERE url = ?1"))?;
+    let mut urls = HashSet::new();
+    let mut mismatches = Vec::new();
+    for visit in visits {
+        let url = visit.url.as_str();
+        if !urls.insert(url) {
+            continue;
+        }
+        let Some(stored) = find_hash.query_row([url], |row| row.get(0)).optional()? else {
+            continue;
+        };
+        let computed = hash::hash(url)?;
+        if stored != computed {
+            mismatches.push(HashMismatch {
+                url: url.to_string(),
+                stored,
+                computed,
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Differences between two places databases.
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DatabaseDiff {
+    /// Rows in moz_places of the first and second database.
+    pub places: (u64, u64),
+    /// Rows in moz_historyvisits of the first and second database.
+    pub visits: (u64, u64),
+    /// URLs of places only the first database has, sorted.
+    pub only_in_first: Vec<String>,
+    /// URLs of places only the second database has, sorted.
+    pub only_in_second: Vec<String>,
+}
+
+/// Compares the places and visits of two databases. Only reads from them.
+pub fn compare(
+    first: &rusqlite::Connection,
+    second: &rusqlite::Connection,
+) -> anyhow::Result<DatabaseDiff> {
+    let count = |connection: &rusqlite::Connection, table: &str| -> anyhow::Result<u64> {
+        Ok(
+            connection.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })?,
+        )
+    };
+    let urls = |connection: &rusqlite::Connection| -> anyhow::Result<HashSet<String>> {
+        Ok(connection
+            .prepare("SELECT url FROM moz_places WHERE url IS NOT NULL")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?)
+    };
+    let (first_urls, second_urls) = (urls(first)?, urls(second)?);
+    let only_in = |a: &HashSet<String>, b: &HashSet<String>| {
+        let mut urls: Vec<String> = a.difference(b).cloned().collect();
+        urls.sort();
+        urls
+    };
+    Ok(DatabaseDiff {
+        places: (count(first, "moz_places")?, count(second, "moz_places")?),
+        visits: (
+            count(first, "moz_historyvisits")?,
+            count(second, "moz_historyvisits")?,
+        ),
+        only_in_first: only_in(&first_urls, &second_urls),
+        only_in_second: only_in(&second_urls, &first_urls),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{compare, diff, exists_in, verify_hashes, DatabaseDiff, HashMismatch, PageDiff};
+    use crate::{hash, places::FirefoxHistoryBuilder, schema_map::SchemaMap, visit::Visit};
+
+    #[test]
+    fn test_compare() {
+        let database = |urls: &[&str]| {
+            let connection = rusqlite::Connection::open_in_memory().unwrap();
+            connection
+                .execute_batch(
+                    "CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url TEXT);
+                    CREATE TABLE moz_historyvisits (id INTEGER PRIMARY KEY, place_id INTEGER);",
+                )
+                .unwrap();
+            for url in urls {
+                connection
+                    .execute("INSERT INTO moz_places (url) VALUES (?1)", [url])
+                    .unwrap();
+                connection
+                    .execute(
+                        "INSERT INTO moz_historyvisits (place_id) VALUES (last_insert_rowid())",
+                        [],
+                    )
+                    .unwrap();
+            }
+            connection
+        };
+        let first = database(&["https://b.com/", "https://a.com/"]);
+        let second = database(&["https://a.com/", "https://c.com/", "https://d.com/"]);
+        assert_eq!(
+            compare(&first, &second).unwrap(),
+            DatabaseDiff {
+                places: (2, 3),
+                visits: (2, 3),
+                only_in_first: vec!["https://b.com/".to_string()],
+                only_in_second: vec!["https://c.com/".to_string(), "https://d.com/".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let path = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-diff-{}.sqlite",
+            std::process::id()
+        ));
+        let existing: Url = "https://www.mozilla.org/".parse().unwrap();
+        let new: Url = "https://search.nixos.org/".parse().unwrap();
+        let mut history = FirefoxHistoryBuilder::new()
+            .create_schema(true)
+            .open(&path)
+            .unwrap();
+        let mut batch = history.begin().unwrap();
+        batch
+            .insert_visit(&Visit::new(existing.clone(), 1000))
+            .unwrap();
+        batch.commit().unwrap();
+        drop(history);
+
+        let visits = [
+            Visit::new(existing.clone(), 1000),
+            Visit::new(existing.clone(), 2000),
+            Visit::new(new.clone(), 3000),
+            Visit::new(new.clone(), 3000),
+            // shares its time with a visit of another page
+            Visit::new(new.clone(), 2000),
+        ];
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let page =
+            |url: &Url, exists, visit_count, new_visit_count, new_visits, duplicates| PageDiff {
+                url: url.to_string(),
+                exists,
+                visit_count,
+                new_visit_count,
+                new_visits,
+                duplicate_visits: duplicates,
+            };
+        assert_eq!(
+            diff(&connection, &visits, false, true, &SchemaMap::default())
+                .unwrap()
+                .pages,
+            [
+                page(&existing, true, 1, 2, 1, 1),
+                page(&new, false, 0, 1, 1, 2)
+            ]
+        );
+        assert_eq!(
+            diff(&connection, &visits, true, false, &SchemaMap::default())
+                .unwrap()
+                .pages,
+            [
+                page(&existing, true, 1, 1, 1, 1),
+                page(&new, false, 0, 2, 2, 1)
+            ]
+        );
+
+        assert_eq!(
+            exists_in(&connection, &visits, &SchemaMap::default()).unwrap(),
+            [true, false, false, false, false]
+        );
+
+        // nothing was written
+        let visits: u32 = connection
+            .query_row("SELECT COUNT(*) FROM moz_historyvisits", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(visits, 1);
+        drop(connection);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn test_verify_hashes() {
+        let path = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-verify-hashes-{}.sqlite",
+            std::process::id()
+        ));
+        let correct: Url = "https://www.mozilla.org/".parse().unwrap();
+        let wrong: Url = "https://search.nixos.org/".parse().unwrap();
+        let new: Url = "https://github.com/".parse().unwrap();
+        let mut history = FirefoxHistoryBuilder::new()
+            .create_schema(true)
+            .open(&path)
+            .unwrap();
+        let mut batch = history.begin().unwrap();
+        batch
+            .insert_visit(&Visit::new(correct.clone(), 1000))
+            .unwrap();
+        batch
+            .insert_visit(&Visit::new(wrong.clone(), 2000))
+            .unwrap();
+        batch.commit().unwrap();
+        drop(history);
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute(
+                "UPDATE moz_places SET url_hash = 42 WHERE url = ?1",
+                [wrong.as_str()],
+            )
+            .unwrap();
+        let visits = [
+            Visit::new(correct.clone(), 3000),
+            Visit::new(wrong.clone(), 4000),
+            Visit::new(wrong.clone(), 5000),
+            Visit::new(new.clone(), 6000),
+        ];
+        assert_eq!(
+            verify_hashes(&connection, &visits, &SchemaMap::default()).unwrap(),
+            [HashMismatch {
+                url: wrong.to_string(),
+                stored: 42,
+                computed: hash::hash(wrong.as_str()).unwrap(),
+            }]
+        );
+        drop(connection);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
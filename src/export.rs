@@ -0,0 +1,152 @@
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
+
+use url::Url;
+
+use crate::visit::Visit;
+
+/// Writes the entries as a Netscape bookmark file, which is the format Firefox's
+/// "Import Bookmarks from HTML" dialog understands.
+///
+/// Each URL is only written once, using its first occurrence in `entries`.
+pub fn write_bookmarks_html(mut writer: impl Write, entries: &[Visit]) -> std::io::Result<()> {
+    writeln!(writer, "<!DOCTYPE NETSCAPE-Bookmark-file-1>")?;
+    writeln!(
+        writer,
+        r#"<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">"#
+    )?;
+    writeln!(writer, "<TITLE>Bookmarks</TITLE>")?;
+    writeln!(writer, "<H1>Bookmarks</H1>")?;
+    writeln!(writer, "<DL><p>")?;
+
+    let mut seen = HashSet::new();
+    for entry in entries {
+        if !seen.insert(entry.url.as_str()) {
+            continue;
+        }
+        let title = entry.title.as_deref().unwrap_or(entry.url.as_str());
+        writeln!(
+            writer,
+            r#"    <DT><A HREF="{}" ADD_DATE="{}">{}</A>"#,
+            escape_html(entry.url.as_str()),
+            entry.time / 1_000_000,
+            escape_html(title)
+        )?;
+    }
+
+    writeln!(writer, "</DL><p>")?;
+    Ok(())
+}
+
+/// Reads the URLs of all bookmarks in a Netscape bookmark file,
+/// like the `Bookmarks.html` of a chrome takeout. Bookmarks with invalid URLs are ignored.
+pub fn read_bookmark_urls(mut reader: impl Read) -> anyhow::Result<HashSet<Url>> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    // lowercasing ASCII keeps the byte offsets the same
+    let lowercase = input.to_ascii_lowercase();
+
+    let mut urls = HashSet::new();
+    let mut rest = 0;
+    while let Some(start) = lowercase[rest..].find("href=\"") {
+        let start = rest + start + "href=\"".len();
+        let Some(end) = input[start..].find('"') else {
+            break;
+        };
+        if let Ok(url) = unescape_html(&input[start..start + end]).parse() {
+            urls.insert(url);
+        }
+        rest = start + end;
+    }
+    Ok(urls)
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{read_bookmark_urls, write_bookmarks_html};
+    use crate::visit::Visit;
+
+    #[test]
+    fn test_write_bookmarks_html() {
+        let url: Url = "https://example.com/?a=1&b=2".parse().unwrap();
+        let entries = [
+            Visit {
+                title: Some("Tom & Jerry <3".to_string()),
+                ..Visit::new(url.clone(), 1737800000123456)
+            },
+            Visit {
+                title: Some("Duplicate".to_string()),
+                ..Visit::new(url, 1737700000000000)
+            },
+            Visit::new(
+                "https://www.mozilla.org/".parse().unwrap(),
+                1737600000000000,
+            ),
+        ];
+        let mut output = Vec::new();
+        write_bookmarks_html(&mut output, &entries).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+        assert!(output.contains(
+            r#"<DT><A HREF="https://example.com/?a=1&amp;b=2" ADD_DATE="1737800000">Tom &amp; Jerry &lt;3</A>"#
+        ));
+        assert!(!output.contains("Duplicate"));
+        assert!(output.contains(
+            r#"<DT><A HREF="https://www.mozilla.org/" ADD_DATE="1737600000">https://www.mozilla.org/</A>"#
+        ));
+    }
+
+    #[test]
+    fn test_read_bookmark_urls() {
+        let input = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3 ADD_DATE="1737800000">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/?a=1&amp;b=2" ADD_DATE="1737800000">Example</A>
+        <DT><a href="https://www.mozilla.org">Mozilla</a>
+        <DT><A HREF="not a url">Broken</A>
+    </DL><p>
+</DL><p>"#;
+        let urls = read_bookmark_urls(input.as_bytes()).unwrap();
+        assert_eq!(urls.len(), 2);
+        assert!(urls.contains(&"https://example.com/?a=1&b=2".parse().unwrap()));
+        assert!(urls.contains(&"https://www.mozilla.org/".parse().unwrap()));
+
+        // reads what write_bookmarks_html writes
+        let entries = [Visit::new(
+            "https://example.com/a\"b".parse().unwrap(),
+            1737800000000000,
+        )];
+        let mut output = Vec::new();
+        write_bookmarks_html(&mut output, &entries).unwrap();
+        let urls = read_bookmark_urls(output.as_slice()).unwrap();
+        assert!(urls.contains(&entries[0].url));
+    }
+}
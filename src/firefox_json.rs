@@ -0,0 +1,141 @@
+//! Reads history exported from Firefox by add-ons, which dump what the WebExtension `history` API returns.
+//!
+//! The input is an array of `HistoryItem`s, each optionally with its `VisitItem`s in `visits`.
+//! Items without visits become a single visit at their `lastVisitTime`.
+//! See: https://developer.mozilla.org/en-US/docs/Mozilla/Add-ons/WebExtensions/API/history
+
+use url::Url;
+
+use crate::{
+    transition::{self, TransitionMap},
+    visit::{Visit, VisitType},
+};
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    pub url: Url,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Milliseconds since the unix epoch, possibly with a fraction.
+    #[serde(default)]
+    pub last_visit_time: Option<f64>,
+    #[serde(default)]
+    pub visits: Option<Vec<VisitItem>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VisitItem {
+    /// Milliseconds since the unix epoch, possibly with a fraction.
+    pub visit_time: f64,
+    /// e.g. `link` or `typed`, the same names Chrome uses for its page transitions.
+    #[serde(default)]
+    pub transition: Option<String>,
+}
+
+fn convert_time(time: f64, url: &Url) -> anyhow::Result<u64> {
+    if !time.is_finite() || time < 0.0 {
+        anyhow::bail!("Visit of {url} has the invalid time {time}.");
+    }
+    Ok((time * 1000.0).round() as u64)
+}
+
+/// Converts the parsed items into visits, in the order of the input.
+pub fn into_visits(items: Vec<HistoryItem>) -> anyhow::Result<Vec<Visit>> {
+    let transitions = TransitionMap::default();
+    let mut visits = Vec::new();
+    for item in items {
+        let title = item.title.filter(|title| !title.is_empty());
+        match item.visits {
+            Some(item_visits) => {
+                for visit in item_visits {
+                    let time = convert_time(visit.visit_time, &item.url)?;
+                    let transition = visit.transition.as_deref().map(transition::name);
+                    visits.push(Visit {
+                        title: title.clone(),
+                        visit_type: transition.as_deref().map_or(VisitType::Link, |transition| {
+                            transitions.visit_type(transition)
+                        }),
+                        transition,
+                        ..Visit::new(item.url.clone(), time)
+                    });
+                }
+            }
+            None => {
+                let Some(time) = item.last_visit_time else {
+                    continue;
+                };
+                let time = convert_time(time, &item.url)?;
+                visits.push(Visit {
+                    title,
+                    ..Visit::new(item.url, time)
+                });
+            }
+        }
+    }
+    Ok(visits)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        takeout::{detect, read, Source},
+        visit::VisitType,
+    };
+
+    #[test]
+    fn test_read_firefox_json() {
+        let input = r#"[
+    {
+        "id": "a1",
+        "url": "https://www.mozilla.org/",
+        "title": "Mozilla",
+        "lastVisitTime": 1737800100000,
+        "visitCount": 2,
+        "visits": [
+            { "visitId": "1", "visitTime": 1737800000123.456, "transition": "typed" },
+            { "visitId": "2", "visitTime": 1737800100000, "transition": "reload" }
+        ]
+    },
+    { "id": "a2", "url": "https://search.nixos.org/", "title": "", "lastVisitTime": 1737700000000 },
+    { "id": "a3", "url": "https://example.com/" }
+]"#;
+        assert_eq!(detect(input), Some(Source::FirefoxJson));
+        let visits = read(input.as_bytes(), None).unwrap();
+        let visits: Vec<(&str, Option<&str>, u64, VisitType)> = visits
+            .iter()
+            .map(|visit| {
+                (
+                    visit.url.as_str(),
+                    visit.title.as_deref(),
+                    visit.time,
+                    visit.visit_type,
+                )
+            })
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                (
+                    "https://www.mozilla.org/",
+                    Some("Mozilla"),
+                    1737800000123456,
+                    VisitType::Typed
+                ),
+                (
+                    "https://www.mozilla.org/",
+                    Some("Mozilla"),
+                    1737800100000000,
+                    VisitType::Reload
+                ),
+                (
+                    "https://search.nixos.org/",
+                    None,
+                    1737700000000000,
+                    VisitType::Link
+                ),
+            ]
+        );
+    }
+}
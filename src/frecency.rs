@@ -0,0 +1,93 @@
+//! Firefox's frecency calculation, so imported places can be ranked before Firefox gets to them.
+//!
+//! This follows `CalculateFrecencyFunction` with the default `places.frecency.*` preferences.
+//! Bookmark and redirect source bonuses are left out, as this tool imports neither.
+//!
+//! See: https://searchfox.org/mozilla-central/source/toolkit/components/places/SQLFunctions.cpp
+
+use crate::visit::VisitType;
+
+/// How many of the most recent visits are sampled.
+pub const SAMPLED_VISITS: usize = 10;
+
+const MICROSECONDS_PER_DAY: u64 = 86_400 * 1_000_000;
+
+/// Weight of a visit by its age in days.
+fn age_weight(age_days: u64) -> f64 {
+    match age_days {
+        0..=4 => 100.0,
+        5..=14 => 70.0,
+        15..=31 => 50.0,
+        32..=90 => 30.0,
+        _ => 10.0,
+    }
+}
+
+/// Bonus of a visit by its `visit_type`, in percent.
+fn visit_bonus(visit_type: u32) -> f64 {
+    const LINK: u32 = VisitType::Link as u32;
+    const TYPED: u32 = VisitType::Typed as u32;
+    const BOOKMARK: u32 = VisitType::Bookmark as u32;
+    const REDIRECT_PERMANENT: u32 = VisitType::RedirectPermanent as u32;
+    match visit_type {
+        LINK => 100.0,
+        TYPED => 2000.0,
+        BOOKMARK => 75.0,
+        REDIRECT_PERMANENT => 50.0,
+        _ => 0.0,
+    }
+}
+
+/// Calculates the frecency of a place.
+///
+/// `visits` are the `(visit_date, visit_type)` of the most recent [`SAMPLED_VISITS`] visits,
+/// `now` is in microseconds since the unix epoch like the visit dates.
+pub fn calculate(visit_count: u32, visits: &[(u64, u32)], now: u64) -> i64 {
+    if visits.is_empty() {
+        return 0;
+    }
+
+    let points: f64 = visits
+        .iter()
+        .map(|&(visit_date, visit_type)| {
+            // Firefox rounds to the nearest day
+            let age_days =
+                (now.saturating_sub(visit_date) + MICROSECONDS_PER_DAY / 2) / MICROSECONDS_PER_DAY;
+            age_weight(age_days) * visit_bonus(visit_type) / 100.0
+        })
+        .sum();
+    if points == 0.0 {
+        // the page was visited, so keep it in the results, just below everything else
+        return -1;
+    }
+
+    let visit_count = f64::from(visit_count.max(1));
+    (visit_count * points.ceil() / visits.len() as f64).ceil() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calculate, MICROSECONDS_PER_DAY};
+
+    #[test]
+    fn test_calculate() {
+        let now = 1737800000000000;
+        let days_ago = |days: u64| now - days * MICROSECONDS_PER_DAY;
+
+        assert_eq!(calculate(0, &[], now), 0);
+        // a single recent link visit
+        assert_eq!(calculate(1, &[(days_ago(1), 1)], now), 100);
+        // a typed visit is worth 20 links
+        assert_eq!(calculate(1, &[(days_ago(1), 2)], now), 2000);
+        // older visits weigh less
+        assert_eq!(calculate(1, &[(days_ago(10), 1)], now), 70);
+        assert_eq!(calculate(1, &[(days_ago(365), 1)], now), 10);
+        // the sample average is scaled by all visits, not only the sampled ones
+        assert_eq!(
+            calculate(20, &[(days_ago(1), 1), (days_ago(20), 1)], now),
+            1500
+        );
+        // visits without a bonus still keep the place visible
+        assert_eq!(calculate(1, &[(days_ago(1), 9)], now), -1);
+    }
+}
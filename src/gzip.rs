@@ -0,0 +1,549 @@
+//! A gzip decoder, so compressed input can be read without decompressing it first.
+//!
+//! Implements inflate as specified in RFC 1951, wrapped in the gzip format of RFC 1952.
+//! Concatenated gzip members are decoded one after another, like `gunzip` does.
+
+use std::io::{self, BufRead, Read};
+
+/// The first bytes of gzip data.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How far back matches may reach.
+const WINDOW_SIZE: usize = 32 * 1024;
+/// The longest Huffman code.
+const MAX_CODE_LENGTH: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const FLAG_HEADER_CRC: u32 = 0x02;
+const FLAG_EXTRA: u32 = 0x04;
+const FLAG_NAME: u32 = 0x08;
+const FLAG_COMMENT: u32 = 0x10;
+const FLAGS_RESERVED: u32 = 0xe0;
+
+/// Decompresses gzip data from a reader.
+pub struct GzipDecoder<R> {
+    input: BitReader<R>,
+    state: State,
+    output: Output,
+    /// Bytes of the current match which are still to be copied, and how far back they are.
+    copy: (usize, usize),
+}
+
+enum State {
+    Header,
+    BlockHeader,
+    Stored {
+        remaining: u16,
+        last: bool,
+    },
+    Compressed {
+        literals: Huffman,
+        distances: Huffman,
+        last: bool,
+    },
+    Trailer,
+    Done,
+}
+
+impl<R: BufRead> GzipDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            input: BitReader {
+                reader,
+                bits: 0,
+                count: 0,
+            },
+            state: State::Header,
+            output: Output {
+                window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+                position: 0,
+                size: 0,
+                crc: !0,
+            },
+            copy: (0, 0),
+        }
+    }
+}
+
+impl<R: BufRead> Read for GzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Self {
+            input,
+            state,
+            output,
+            copy,
+        } = self;
+        let mut written = 0;
+        while written < buf.len() {
+            if copy.0 > 0 {
+                let byte = output.back(copy.1);
+                buf[written] = byte;
+                written += 1;
+                output.push(byte);
+                copy.0 -= 1;
+                continue;
+            }
+            match state {
+                State::Header => {
+                    read_header(input)?;
+                    // each member starts over
+                    output.size = 0;
+                    output.crc = !0;
+                    *state = State::BlockHeader;
+                }
+                State::BlockHeader => {
+                    let last = input.bits(1)? == 1;
+                    *state = match input.bits(2)? {
+                        0 => {
+                            input.align();
+                            let length = input.bits(16)? as u16;
+                            if input.bits(16)? as u16 != !length {
+                                return Err(invalid(
+                                    "stored block length doesn't match its complement",
+                                ));
+                            }
+                            State::Stored {
+                                remaining: length,
+                                last,
+                            }
+                        }
+                        1 => State::Compressed {
+                            literals: Huffman::fixed_literals(),
+                            distances: Huffman::fixed_distances(),
+                            last,
+                        },
+                        2 => {
+                            let (literals, distances) = read_dynamic_codes(input)?;
+                            State::Compressed {
+                                literals,
+                                distances,
+                                last,
+                            }
+                        }
+                        _ => return Err(invalid("invalid block type")),
+                    };
+                }
+                State::Stored { remaining, last } => {
+                    if *remaining == 0 {
+                        *state = end_of_block(*last);
+                        continue;
+                    }
+                    *remaining -= 1;
+                    let byte = input.bits(8)? as u8;
+                    buf[written] = byte;
+                    written += 1;
+                    output.push(byte);
+                }
+                State::Compressed {
+                    literals,
+                    distances,
+                    last,
+                } => {
+                    let symbol = literals.decode(input)? as usize;
+                    match symbol {
+                        0..=255 => {
+                            buf[written] = symbol as u8;
+                            written += 1;
+                            output.push(symbol as u8);
+                        }
+                        256 => *state = end_of_block(*last),
+                        257..=285 => {
+                            let index = symbol - 257;
+                            let length = LENGTH_BASE[index] as usize
+                                + input.bits(LENGTH_EXTRA[index].into())? as usize;
+                            let index = distances.decode(input)? as usize;
+                            if index >= DISTANCE_BASE.len() {
+                                return Err(invalid("invalid distance code"));
+                            }
+                            let distance = DISTANCE_BASE[index] as usize
+                                + input.bits(DISTANCE_EXTRA[index].into())? as usize;
+                            if distance as u64 > output.size.min(WINDOW_SIZE as u64) {
+                                return Err(invalid("distance reaches before the start"));
+                            }
+                            *copy = (length, distance);
+                        }
+                        _ => return Err(invalid("invalid literal or length code")),
+                    }
+                }
+                State::Trailer => {
+                    input.align();
+                    let crc = input.bits(32)?;
+                    let size = input.bits(32)?;
+                    if crc != !output.crc {
+                        return Err(invalid("checksum mismatch"));
+                    }
+                    if size != output.size as u32 {
+                        return Err(invalid("size mismatch"));
+                    }
+                    // another member may follow
+                    *state = if input.at_end()? {
+                        State::Done
+                    } else {
+                        State::Header
+                    };
+                }
+                State::Done => break,
+            }
+        }
+        Ok(written)
+    }
+}
+
+fn end_of_block(last: bool) -> State {
+    if last {
+        State::Trailer
+    } else {
+        State::BlockHeader
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid gzip data, {message}."),
+    )
+}
+
+fn read_header(input: &mut BitReader<impl BufRead>) -> io::Result<()> {
+    if input.bits(8)? != u32::from(MAGIC[0]) || input.bits(8)? != u32::from(MAGIC[1]) {
+        return Err(invalid("the magic bytes are missing"));
+    }
+    if input.bits(8)? != 8 {
+        return Err(invalid("the compression method isn't deflate"));
+    }
+    let flags = input.bits(8)?;
+    if flags & FLAGS_RESERVED != 0 {
+        return Err(invalid("reserved flags are set"));
+    }
+    // modification time, extra flags and operating system
+    input.bits(32)?;
+    input.bits(16)?;
+    if flags & FLAG_EXTRA != 0 {
+        let length = input.bits(16)?;
+        for _ in 0..length {
+            input.bits(8)?;
+        }
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            // zero-terminated
+            while input.bits(8)? != 0 {}
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        input.bits(16)?;
+    }
+    Ok(())
+}
+
+fn read_dynamic_codes(input: &mut BitReader<impl BufRead>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("a length repeats before the first one"))?;
+                (previous, 3 + input.bits(2)?)
+            }
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+        if lengths.len() + repeat as usize > literal_count + distance_count {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths.resize(lengths.len() + repeat as usize, length);
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("the end of block code is missing"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// The last [`WINDOW_SIZE`] bytes of output, and the checksum of all of it.
+struct Output {
+    window: Box<[u8]>,
+    position: usize,
+    size: u64,
+    crc: u32,
+}
+
+impl Output {
+    fn push(&mut self, byte: u8) {
+        self.window[self.position] = byte;
+        self.position = (self.position + 1) % WINDOW_SIZE;
+        self.size += 1;
+        self.crc = CRC_TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+    }
+
+    fn back(&self, distance: usize) -> u8 {
+        self.window[(self.position + WINDOW_SIZE - distance) % WINDOW_SIZE]
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// The table of the CRC-32 gzip uses, with the reversed polynomial 0xedb88320.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Reads bits in the order deflate packs them, starting at the least significant bit of each byte.
+struct BitReader<R> {
+    reader: R,
+    bits: u64,
+    count: u32,
+}
+
+impl<R: BufRead> BitReader<R> {
+    /// Fills up the buffered bits, there are fewer only at the end of the input.
+    fn refill(&mut self) -> io::Result<()> {
+        while self.count <= 56 {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let take = (((64 - self.count) / 8) as usize).min(available.len());
+            for byte in &available[..take] {
+                self.bits |= u64::from(*byte) << self.count;
+                self.count += 8;
+            }
+            self.reader.consume(take);
+        }
+        Ok(())
+    }
+
+    /// Returns the next `count` bits without consuming them, padded with zeros at the end of the input.
+    fn peek(&mut self, count: u32) -> io::Result<u32> {
+        if self.count < count {
+            self.refill()?;
+        }
+        Ok((self.bits & ((1 << count) - 1)) as u32)
+    }
+
+    fn consume(&mut self, count: u32) -> io::Result<()> {
+        if count > self.count {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The gzip data ends unexpectedly, it may be truncated.",
+            ));
+        }
+        self.bits >>= count;
+        self.count -= count;
+        Ok(())
+    }
+
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        let bits = self.peek(count)?;
+        self.consume(count)?;
+        Ok(bits)
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        let count = self.count % 8;
+        self.bits >>= count;
+        self.count -= count;
+    }
+
+    /// Whether all input was read, at a byte boundary.
+    fn at_end(&mut self) -> io::Result<bool> {
+        if self.count < 8 {
+            self.refill()?;
+        }
+        Ok(self.count < 8)
+    }
+}
+
+/// A canonical Huffman code, decoded by looking up the next bits in a table.
+struct Huffman {
+    /// The symbol and length of the code at the start of each combination of the next `bits` bits,
+    /// as `symbol << 4 | length`, with a length of 0 if no code matches.
+    table: Vec<u16>,
+    bits: u32,
+}
+
+impl Huffman {
+    /// Builds the code from the code length of each symbol, 0 for unused symbols.
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        // codes left at each length, more codes than that can't be told apart
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = left * 2 - i32::from(*count);
+            if left < 0 {
+                return Err(invalid("a Huffman code has more codes than fit"));
+            }
+        }
+
+        let mut next = [0u32; MAX_CODE_LENGTH + 1];
+        let mut code = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code = (code + u32::from(counts[length - 1])) << 1;
+            next[length] = code;
+        }
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let mut table = vec![0; 1 << bits];
+        for (symbol, length) in lengths.iter().enumerate() {
+            let length = *length as usize;
+            if length == 0 {
+                continue;
+            }
+            let code = next[length];
+            next[length] += 1;
+            // codes are packed starting at their most significant bit
+            let reversed = code.reverse_bits() >> (32 - length);
+            let entry = (symbol as u16) << 4 | length as u16;
+            for index in (reversed as usize..table.len()).step_by(1 << length) {
+                table[index] = entry;
+            }
+        }
+        Ok(Self { table, bits })
+    }
+
+    fn fixed_literals() -> Self {
+        let mut lengths = [8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        Self::new(&lengths).expect("the fixed code is valid")
+    }
+
+    fn fixed_distances() -> Self {
+        Self::new(&[5; 30]).expect("the fixed code is valid")
+    }
+
+    fn decode(&self, input: &mut BitReader<impl BufRead>) -> io::Result<u16> {
+        let entry = self.table[input.peek(self.bits)? as usize];
+        let length = entry & 0xf;
+        if length == 0 {
+            return Err(invalid("invalid code"));
+        }
+        input.consume(length.into())?;
+        Ok(entry >> 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+
+    use super::GzipDecoder;
+
+    /// `hello hello hello hello\n` in a single block with the fixed code.
+    const FIXED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x00, 0x88, 0x59, 0x0b, 0x18, 0x00, 0x00, 0x00,
+    ];
+    /// `stored\n` in a stored block.
+    const STORED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x01, 0x07, 0x00, 0xf8, 0xff,
+        0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x0a, 0xe2, 0x9c, 0x53, 0xa5, 0x07, 0x00, 0x00, 0x00,
+    ];
+
+    fn decompress(input: &[u8]) -> std::io::Result<String> {
+        let mut output = String::new();
+        GzipDecoder::new(input).read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(decompress(FIXED).unwrap(), "hello hello hello hello\n");
+        assert_eq!(decompress(STORED).unwrap(), "stored\n");
+
+        // with a file name in the header
+        let mut named = FIXED[..10].to_vec();
+        named[3] = 0x08;
+        named.extend(b"hello.txt\0");
+        named.extend(&FIXED[10..]);
+        assert_eq!(decompress(&named).unwrap(), "hello hello hello hello\n");
+
+        // members are concatenated
+        assert_eq!(
+            decompress(&[FIXED, STORED].concat()).unwrap(),
+            "hello hello hello hello\nstored\n"
+        );
+
+        // dynamic codes, written by gzip -9
+        let history = include_str!("../tests/fixtures/History.json");
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/History.json.gz")).unwrap(),
+            history
+        );
+        // matches across the end of the window
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/History-x100.json.gz")).unwrap(),
+            history.repeat(100)
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = decompress(&FIXED[..20]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        let mut corrupted = FIXED.to_vec();
+        corrupted[22] ^= 1;
+        let error = decompress(&corrupted).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid gzip data, checksum mismatch.");
+
+        let error = decompress(b"{}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid gzip data, the magic bytes are missing."
+        );
+    }
+}
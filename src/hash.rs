@@ -1,5 +1,6 @@
 use anyhow::Context;
 
+/// Computes `moz_places.url_hash` like Firefox's `hash()` SQL function.
 pub fn hash(url: &str) -> anyhow::Result<u64> {
     let prefix = url.find(':').context("URL is missing the protocol.")?;
     let result = ((hash_simple(&url[0..prefix]) & 0x0000FFFF) << 32) + hash_simple(url);
@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use url::Url;
+
+use crate::visit::EntryError;
+
+/// Schemes Firefox never adds to its history, see `nsNavHistory::CanAddURI`.
+const UNSUPPORTED_SCHEMES: &[&str] = &[
+    "about",
+    "blob",
+    "chrome",
+    "data",
+    "imap",
+    "javascript",
+    "mailbox",
+    "moz-anno",
+    "moz-extension",
+    "news",
+    "page-icon",
+    "resource",
+    "view-source",
+];
+
+/// Longest URL Firefox adds to its history by default, see `nsNavHistory::CanAddURI`.
+pub const MAX_URL_LENGTH: usize = 65536;
+
+/// Why a URL can't be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    UnsupportedScheme,
+    TooLong,
+    NoHost,
+    OpaqueOrigin,
+}
+
+pub fn check(url: &Url) -> Option<Problem> {
+    if UNSUPPORTED_SCHEMES.contains(&url.scheme()) {
+        Some(Problem::UnsupportedScheme)
+    } else if url.as_str().len() > MAX_URL_LENGTH {
+        Some(Problem::TooLong)
+    } else if url.host_str().is_none_or(str::is_empty) {
+        Some(Problem::NoHost)
+    } else if !url.origin().is_tuple() {
+        Some(Problem::OpaqueOrigin)
+    } else {
+        None
+    }
+}
+
+/// Whether Firefox would store the URL in its history at all.
+/// Entries whose URL it wouldn't are never matched by Firefox, so they shouldn't be imported.
+///
+/// The URL is otherwise already in the form Firefox stores, because the `url` crate follows the same
+/// URL standard as Firefox's parser. When parsing it
+/// - lowercases the scheme and, for http(s), ws(s) and ftp, the host
+/// - converts internationalized hosts to punycode and normalizes IPv4 addresses like `0x7f.1`
+/// - drops default ports and an empty password
+/// - turns `\` into `/` and resolves `.` and `..` segments, also percent-encoded ones
+/// - percent-encodes spaces and other characters which aren't allowed where they are
+/// - removes tabs and newlines anywhere and other whitespace around the URL
+pub fn is_storable(url: &Url) -> bool {
+    !matches!(
+        check(url),
+        Some(Problem::UnsupportedScheme | Problem::TooLong)
+    )
+}
+
+/// Counts of problematic URLs in the input, reported before anything is written.
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    pub total: usize,
+    pub unsupported_scheme: usize,
+    pub too_long: usize,
+    pub no_host: usize,
+    pub opaque_origin: usize,
+}
+
+impl HealthReport {
+    pub fn new<'a>(urls: impl IntoIterator<Item = &'a Url>) -> Self {
+        let mut report = Self::default();
+        for url in urls {
+            report.total += 1;
+            match check(url) {
+                Some(Problem::UnsupportedScheme) => report.unsupported_scheme += 1,
+                Some(Problem::TooLong) => report.too_long += 1,
+                Some(Problem::NoHost) => report.no_host += 1,
+                Some(Problem::OpaqueOrigin) => report.opaque_origin += 1,
+                None => {}
+            }
+        }
+        report
+    }
+
+    pub fn problems(&self) -> usize {
+        self.unsupported_scheme + self.too_long + self.no_host + self.opaque_origin
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Entries: {}", self.total)?;
+        writeln!(f, "Unsupported scheme: {}", self.unsupported_scheme)?;
+        writeln!(f, "Too long: {}", self.too_long)?;
+        writeln!(f, "No host: {}", self.no_host)?;
+        write!(f, "Opaque origin: {}", self.opaque_origin)
+    }
+}
+
+/// Entries which don't make it into the database, grouped by why, for `--report-unsupported`.
+///
+/// Next to the entries [`crate::visit::Visit::validate`] rejects, this counts those with an opaque origin,
+/// which pass validation but are skipped when importing.
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct UnsupportedReport {
+    pub total: usize,
+    /// Entries with a scheme Firefox doesn't store, by scheme.
+    pub unsupported_schemes: BTreeMap<String, usize>,
+    /// Entries with a URL longer than [`MAX_URL_LENGTH`], by host.
+    pub too_long: BTreeMap<String, usize>,
+    /// Entries with a URL without a host, by scheme.
+    pub no_host: BTreeMap<String, usize>,
+    /// Entries with an opaque origin, by scheme.
+    pub opaque_origins: BTreeMap<String, usize>,
+    /// Entries with an implausible time, by the year of the time counted from the Unix epoch, e.g. 1970 for times of 0.
+    pub implausible_times: BTreeMap<String, usize>,
+}
+
+impl UnsupportedReport {
+    /// Counts an entry with `url` which was rejected with `error`.
+    pub fn add_error(&mut self, url: &Url, error: &EntryError) {
+        let (group, key) = match error {
+            EntryError::UnsupportedScheme(scheme) => {
+                (&mut self.unsupported_schemes, scheme.clone())
+            }
+            EntryError::UrlTooLong(_) => (
+                &mut self.too_long,
+                url.host_str().unwrap_or_default().to_string(),
+            ),
+            EntryError::NoHost | EntryError::FileUrl => {
+                (&mut self.no_host, url.scheme().to_string())
+            }
+            EntryError::ImplausibleTime(time) => (
+                &mut self.implausible_times,
+                crate::time::format_utc(*time)[..4].to_string(),
+            ),
+        };
+        *group.entry(key).or_default() += 1;
+        self.total += 1;
+    }
+
+    /// Counts an entry with `url` if it has an opaque origin.
+    pub fn add_if_opaque(&mut self, url: &Url) {
+        if check(url) == Some(Problem::OpaqueOrigin) {
+            *self
+                .opaque_origins
+                .entry(url.scheme().to_string())
+                .or_default() += 1;
+            self.total += 1;
+        }
+    }
+}
+
+/// Finds origins with more than `threshold` distinct URLs, which are often the same pages
+/// with different query parameters. Returns the origins with their URL counts, most URLs first.
+pub fn place_explosions<'a>(
+    urls: impl IntoIterator<Item = &'a Url>,
+    threshold: usize,
+) -> Vec<(String, usize)> {
+    let mut origins: HashMap<String, HashSet<&str>> = HashMap::new();
+    for url in urls {
+        origins
+            .entry(url.origin().ascii_serialization())
+            .or_default()
+            .insert(url.as_str());
+    }
+    let mut explosions: Vec<(String, usize)> = origins
+        .into_iter()
+        .map(|(origin, urls)| (origin, urls.len()))
+        .filter(|(_, count)| *count > threshold)
+        .collect();
+    explosions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    explosions
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{
+        check, is_storable, place_explosions, HealthReport, Problem, UnsupportedReport,
+        MAX_URL_LENGTH,
+    };
+    use crate::visit::Visit;
+
+    #[test]
+    fn test_check() {
+        let cases = [
+            ("https://www.mozilla.org/", None),
+            ("http://localhost:8080/", None),
+            ("about:blank", Some(Problem::UnsupportedScheme)),
+            ("data:text/plain,hi", Some(Problem::UnsupportedScheme)),
+            ("javascript:alert(1)", Some(Problem::UnsupportedScheme)),
+            ("file:///home/user/index.html", Some(Problem::NoHost)),
+            ("mailto:someone@example.com", Some(Problem::NoHost)),
+            ("git://example.com/repo", Some(Problem::OpaqueOrigin)),
+        ];
+        for (url, problem) in cases {
+            assert_eq!(check(&url.parse().unwrap()), problem, "{url}");
+        }
+
+        let long = format!("https://example.com/{}", "a".repeat(MAX_URL_LENGTH));
+        assert_eq!(check(&long.parse().unwrap()), Some(Problem::TooLong));
+    }
+
+    #[test]
+    fn test_is_storable() {
+        let cases = [
+            ("https://www.mozilla.org/", true),
+            ("about:config", false),
+            ("view-source:https://www.mozilla.org/", false),
+            ("moz-extension://1234/popup.html", false),
+            // the importer skips these itself
+            ("file:///home/user/index.html", true),
+        ];
+        for (url, storable) in cases {
+            assert_eq!(is_storable(&url.parse().unwrap()), storable, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_firefox_canonical_form() {
+        // what Firefox stores for these, see the documentation of is_storable
+        let cases = [
+            ("HTTPS://WWW.Mozilla.ORG:443/", "https://www.mozilla.org/"),
+            ("http://user:@example.com", "http://user@example.com/"),
+            ("https://Bücher.example/", "https://xn--bcher-kva.example/"),
+            ("http://0x7f.1/", "http://127.0.0.1/"),
+            (
+                "https://example.com\\a/./b/%2e%2E/c",
+                "https://example.com/a/c",
+            ),
+            (
+                " https://example.com/a b?c d#e f\n",
+                "https://example.com/a%20b?c%20d#e%20f",
+            ),
+            ("https://exa\tmple.com/", "https://example.com/"),
+        ];
+        for (input, expected) in cases {
+            let url: Url = input.parse().unwrap();
+            assert_eq!(url.as_str(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let urls: Vec<Url> = ["https://www.mozilla.org/", "about:blank", "file:///a"]
+            .iter()
+            .map(|url| url.parse().unwrap())
+            .collect();
+        let report = HealthReport::new(&urls);
+        assert_eq!(report.total, 3);
+        assert_eq!(report.unsupported_scheme, 1);
+        assert_eq!(report.too_long, 0);
+        assert_eq!(report.no_host, 1);
+        assert_eq!(report.opaque_origin, 0);
+        assert_eq!(report.problems(), 2);
+    }
+
+    #[test]
+    fn test_unsupported_report() {
+        let long = format!("https://example.com/{}", "a".repeat(MAX_URL_LENGTH));
+        let entries = [
+            ("https://www.mozilla.org/", 1737800000000000),
+            ("about:blank", 1737800000000000),
+            ("about:config", 1737800000000000),
+            ("chrome://settings/", 1737800000000000),
+            ("file:///a", 1737800000000000),
+            (long.as_str(), 1737800000000000),
+            ("git://example.com/repo", 1737800000000000),
+            ("https://www.mozilla.org/", 0),
+        ];
+        let mut report = UnsupportedReport::default();
+        for (url, time) in entries {
+            let url: Url = url.parse().unwrap();
+            match Visit::new(url.clone(), time).validate() {
+                Ok(_) => report.add_if_opaque(&url),
+                Err(error) => report.add_error(&url, &error),
+            }
+        }
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "total": 7,
+                "unsupported_schemes": { "about": 2, "chrome": 1 },
+                "too_long": { "example.com": 1 },
+                "no_host": { "file": 1 },
+                "opaque_origins": { "git": 1 },
+                "implausible_times": { "1970": 1 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_place_explosions() {
+        let urls: Vec<Url> = [
+            "https://www.google.com/search?q=a",
+            "https://www.google.com/search?q=b",
+            "https://www.google.com/search?q=c",
+            "https://www.google.com/search?q=c",
+            "https://www.mozilla.org/",
+            "https://www.mozilla.org/about/",
+            "http://www.mozilla.org/",
+        ]
+        .iter()
+        .map(|url| url.parse().unwrap())
+        .collect();
+        assert_eq!(
+            place_explosions(&urls, 1),
+            [
+                ("https://www.google.com".to_string(), 3),
+                ("https://www.mozilla.org".to_string(), 2),
+            ]
+        );
+        assert!(place_explosions(&urls, 3).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+use crate::gzip::{self, GzipDecoder};
+
+/// Opens the input file, or stdin if `path` is `-`. Gzip compressed input is decompressed while reading.
+pub fn open(path: &Path) -> anyhow::Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    if reader.fill_buf()?.starts_with(&gzip::MAGIC) {
+        reader = Box::new(BufReader::new(GzipDecoder::new(reader)));
+    }
+    Ok(reader)
+}
+
+/// Maps the input file into memory, which saves copying huge files into a buffer.
+///
+/// Returns `None` if the file can't be mapped, e.g. because `path` is `-`, a pipe or empty,
+/// or if it is gzip compressed, in which case it should be read with [`open`] instead.
+pub fn map(path: &Path) -> anyhow::Result<Option<Mmap>> {
+    if path.as_os_str() == "-" {
+        return Ok(None);
+    }
+    let file = File::open(path)?;
+    // SAFETY: the map is only read while parsing, if another process truncates the file meanwhile
+    // we crash with SIGBUS, like many other tools. There is no way to prevent that for files we don't own.
+    let Ok(map) = (unsafe { Mmap::map(&file) }) else {
+        return Ok(None);
+    };
+    if map.is_empty() || map.starts_with(&gzip::MAGIC) {
+        return Ok(None);
+    }
+    Ok(Some(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, path::Path};
+
+    #[test]
+    fn test_open_gzip() {
+        let mut input = String::new();
+        super::open(Path::new("tests/fixtures/History.json.gz"))
+            .unwrap()
+            .read_to_string(&mut input)
+            .unwrap();
+        assert_eq!(input, include_str!("../tests/fixtures/History.json"));
+        assert!(super::map(Path::new("tests/fixtures/History.json.gz"))
+            .unwrap()
+            .is_none());
+    }
+}
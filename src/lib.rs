@@ -0,0 +1,30 @@
+pub mod activity;
+pub mod csv;
+pub mod diff;
+pub mod export;
+pub mod firefox_json;
+pub mod frecency;
+pub mod gzip;
+pub mod hash;
+pub mod health;
+pub mod input;
+pub mod manifest;
+pub mod noise;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod places;
+pub mod profiles;
+pub mod regex;
+pub mod report;
+pub mod resume;
+pub mod rewrite;
+pub mod safari;
+pub mod safe_write;
+pub mod schema_map;
+pub mod sync;
+pub mod takeout;
+pub mod time;
+#[cfg(feature = "transform")]
+pub mod transform;
+pub mod transition;
+pub mod visit;
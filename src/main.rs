@@ -1,261 +1,1678 @@
 use std::{
-    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fs::File,
-    io::BufReader,
+    io::{BufWriter, IsTerminal},
     path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use base64::Engine;
+use anyhow::Context;
+use chrome_takeout_to_firefox::{
+    activity, csv,
+    diff::{self, DatabaseDiff, Diff},
+    export, hash,
+    health::{self, HealthReport, UnsupportedReport},
+    input,
+    manifest::{self, ImportRecord},
+    noise::NoiseRules,
+    places::{
+        Counts, DuplicatePolicy, EmptyTitleMode, FirefoxHistory, FirefoxHistoryBuilder,
+        GuidConflict, ImportOptions, JournalMode, Synchronous,
+    },
+    profiles::{self, TargetApp},
+    regex::Regex,
+    report::{self, ImportStats},
+    resume::ResumeState,
+    rewrite::{self, FragmentMode, TitleRewrite, TitleSource},
+    safe_write::{self, SafeWrite},
+    schema_map::SchemaMap,
+    takeout, time,
+    transition::TransitionMap,
+    visit::Visit,
+};
 use clap::Parser;
 use indicatif::ProgressBar;
-use rand::RngCore;
-use rusqlite::{OptionalExtension, Transaction};
-use url::Url;
 
-mod hash;
+/// Exit code of an import which succeeded, but didn't add any visits although it had entries,
+/// e.g. because they all existed already. Input without entries, or whose entries were all
+/// filtered out, had nothing to import and exits with success.
+const EXIT_NOTHING_INSERTED: u8 = 3;
+/// Exit code of an import which finished, but failed to convert some entries.
+const EXIT_PARTIAL_FAILURE: u8 = 4;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<ExitCode> {
     let cli = Cli::parse();
 
-    let mut history = FirefoxHistory::open_file(&cli.sqlite_db)?;
+    match cli.command {
+        Some(Command::ListProfiles { target_app }) => {
+            return list_profiles(target_app).map(|()| ExitCode::SUCCESS)
+        }
+        Some(Command::SelfTest) => return self_test().map(|()| ExitCode::SUCCESS),
+        Some(Command::Diff {
+            first,
+            second,
+            format,
+        }) => return diff_databases(&first, &second, format).map(|()| ExitCode::SUCCESS),
+        Some(Command::RemoveTag { label, places }) => {
+            return remove_tag(&places, &label).map(|()| ExitCode::SUCCESS)
+        }
+        Some(Command::History { places, format }) => {
+            return show_history(&places, format).map(|()| ExitCode::SUCCESS)
+        }
+        Some(Command::GenerateHashVectors { places, count }) => {
+            return generate_hash_vectors(&places, count).map(|()| ExitCode::SUCCESS)
+        }
+        None => {}
+    }
+    let history_path = cli
+        .chrome_takeout_history_path
+        .as_deref()
+        .expect("The history path is required without a subcommand.");
 
-    let file = BufReader::new(File::open(cli.chrome_takeout_history_path)?);
+    let title_rewrites = cli
+        .title_rewrite
+        .chunks(2)
+        .map(|rule| {
+            Ok(TitleRewrite {
+                regex: rule[0]
+                    .parse()
+                    .with_context(|| format!("Invalid --title-rewrite pattern {:?}.", rule[0]))?,
+                replacement: rule[1].clone(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    let takeout: ChromeTakeoutFile = serde_json::from_reader(file)?;
+    let start = Instant::now();
+    let map = if cli.mmap {
+        input::map(history_path)?
+    } else {
+        None
+    };
+    let mut entries = match &map {
+        Some(map) => std::str::from_utf8(map)
+            .map_err(anyhow::Error::from)
+            .and_then(|input| {
+                if cli.import_csv {
+                    csv::parse_history(input)
+                } else {
+                    takeout::parse_history(input, cli.source)
+                }
+            }),
+        None => {
+            let file = input::open(history_path)?;
+            if cli.import_csv {
+                csv::read_history(file)
+            } else {
+                takeout::read(file, cli.source)
+            }
+        }
+    }
+    .with_context(|| format!("Failed to parse {}.", history_path.display()))?;
+    drop(map);
+    let resume = match &cli.state_file {
+        Some(path) => ResumeState::load(path)?,
+        None => None,
+    };
+    // a resumed import has to skip the same entries as the interrupted one
+    let entry_offset = resume
+        .as_ref()
+        .map(|state| state.entry_offset)
+        .or(cli.entry_offset);
+    if let Some(offset) = entry_offset.filter(|offset| *offset > 0) {
+        let total = entries.len();
+        if offset < total {
+            entries.drain(..offset);
+            eprintln!(
+                "Skipped the first {offset} entries, processing entries {} to {total}.",
+                offset + 1
+            );
+        } else {
+            entries.clear();
+            eprintln!("Skipped all {total} entries, the offset is past the end of the input.");
+        }
+    }
+    if let Some(path) = &cli.transition_map {
+        TransitionMap::from_file(path)?.apply(&mut entries);
+    }
+    if cli.redirects {
+        let added = rewrite::expand_redirects(&mut entries);
+        eprintln!("Added {added} entries for pages which redirected to another one.");
+    } else {
+        for entry in entries.iter_mut() {
+            entry.redirects.clear();
+        }
+    }
+    if let Some(path) = &cli.include_searches {
+        let searches = activity::read_searches(input::open(path)?)
+            .with_context(|| format!("Failed to parse {}.", path.display()))?;
+        eprintln!("Including {} searches.", searches.len());
+        entries.extend(searches);
+    }
+    if let Some(shift) = cli.time_shift {
+        for entry in entries.iter_mut() {
+            entry.time = time::shift(entry.time, shift);
+        }
+    }
+    let parsing = start.elapsed();
 
-    let progress = ProgressBar::new(takeout.history.len() as u64);
+    if let Some(fraction) = cli.sample {
+        let before = entries.len();
+        rewrite::sample(&mut entries, fraction, cli.sample_seed);
+        eprintln!(
+            "Sampled {} of {} entries with seed {}.",
+            entries.len(),
+            before,
+            cli.sample_seed
+        );
+    }
 
-    for chunk in takeout.history.chunks(1000) {
-        let mut batch = history.begin()?;
-        for entry in chunk {
-            let title = if entry.title.is_empty() {
-                None
-            } else {
-                Some(entry.title.as_str())
-            };
-            let result = batch.insert_visit(&entry.url, title, entry.time_usec);
+    if let Some(count) = cli.preview {
+        for (index, entry) in entries.iter().take(count).enumerate() {
+            println!("{}. {}", index + 1, entry.url);
+            println!("   Title: {}", entry.title.as_deref().unwrap_or("(none)"));
+            println!(
+                "   Time: {} ({})",
+                time::format_in(entry.time, cli.tz),
+                entry.time
+            );
+        }
+        println!("Parsed {} entries in total.", entries.len());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(domains) = &cli.upgrade_http {
+        for entry in entries.iter_mut() {
+            rewrite::upgrade_http(&mut entry.url, domains);
+        }
+    }
+
+    if cli.fragments == FragmentMode::Strip {
+        let mut stripped = 0;
+        for entry in entries.iter_mut() {
+            if rewrite::strip_fragment(&mut entry.url) {
+                stripped += 1;
+            }
+            if let Some(referrer) = &mut entry.referrer {
+                rewrite::strip_fragment(referrer);
+            }
+            for redirect in entry.redirects.iter_mut() {
+                rewrite::strip_fragment(redirect);
+            }
+        }
+        eprintln!("Removed the fragment of {stripped} entries.");
+    }
+
+    if let Some(keep) = &cli.merge_queries {
+        let mut merged = 0;
+        for entry in entries.iter_mut() {
+            if rewrite::merge_query(&mut entry.url, keep) {
+                merged += 1;
+            }
+        }
+        eprintln!("Removed query parameters from {merged} entries.");
+    }
+
+    if !cli.url_filter.is_empty() || !cli.url_exclude.is_empty() {
+        let before = entries.len();
+        entries.retain(|entry| {
+            let url = entry.url.as_str();
+            (cli.url_filter.is_empty() || cli.url_filter.iter().any(|regex| regex.is_match(url)))
+                && !cli.url_exclude.iter().any(|regex| regex.is_match(url))
+        });
+        eprintln!(
+            "Filtered out {} of {} entries by URL.",
+            before - entries.len(),
+            before
+        );
+    }
+
+    if cli.exclude_noise {
+        let defaults = NoiseRules::default();
+        let rules = NoiseRules {
+            schemes: cli.noise_scheme.clone().unwrap_or(defaults.schemes),
+            titles: cli.noise_title.clone().unwrap_or(defaults.titles),
+            min_duration: cli.noise_min_duration.unwrap_or(defaults.min_duration),
+        };
+        let before = entries.len();
+        entries.retain(|entry| !rules.is_noise(entry));
+        eprintln!(
+            "Filtered out {} of {} entries as noise.",
+            before - entries.len(),
+            before
+        );
+    }
+
+    if let Some(path) = &cli.only_bookmarked {
+        let bookmarks = export::read_bookmark_urls(input::open(path)?)
+            .with_context(|| format!("Failed to parse {}.", path.display()))?;
+        let before = entries.len();
+        entries.retain(|entry| bookmarks.contains(&entry.url));
+        let urls: HashSet<&str> = entries.iter().map(|entry| entry.url.as_str()).collect();
+        eprintln!(
+            "Kept {} of {} entries, {} of {} bookmarks are in the history.",
+            entries.len(),
+            before,
+            urls.len(),
+            bookmarks.len()
+        );
+    }
+
+    if let Some(min_visit_count) = cli.min_visit_count {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for entry in &entries {
+            *counts.entry(entry.url.as_str()).or_default() += 1;
+        }
+        let frequent: HashSet<String> = counts
+            .iter()
+            .filter(|(_, count)| **count >= min_visit_count)
+            .map(|(url, _)| url.to_string())
+            .collect();
+        let urls = counts.len();
+        entries.retain(|entry| frequent.contains(entry.url.as_str()));
+        eprintln!(
+            "Filtered out {} of {} URLs visited fewer than {} times.",
+            urls - frequent.len(),
+            urls,
+            min_visit_count
+        );
+    }
+
+    if cli.fix_mojibake {
+        let mut fixed = 0;
+        for entry in entries.iter_mut() {
+            if let Some(title) = entry.title.as_deref().and_then(rewrite::fix_mojibake) {
+                entry.title = Some(title);
+                fixed += 1;
+            }
+        }
+        eprintln!("Repaired {fixed} titles which were decoded with the wrong encoding.");
+    }
+
+    if !title_rewrites.is_empty() {
+        for entry in entries.iter_mut() {
+            TitleRewrite::apply_all(&title_rewrites, &mut entry.title);
+        }
+    }
+
+    #[cfg(feature = "transform")]
+    if let Some(path) = &cli.transform {
+        let transform = chrome_takeout_to_firefox::transform::Transform::from_file(path)?;
+        let before = entries.len();
+        let mut kept = Vec::with_capacity(before);
+        for (index, mut entry) in entries.into_iter().enumerate() {
+            if transform
+                .apply(&mut entry)
+                .with_context(|| format!("{} failed on entry {}.", path.display(), index + 1))?
+            {
+                kept.push(entry);
+            }
+        }
+        entries = kept;
+        eprintln!(
+            "The transform skipped {} of {} entries.",
+            before - entries.len(),
+            before
+        );
+    }
+
+    if let Some(min_title_length) = cli.min_title_length {
+        let before = entries.len();
+        entries.retain(|entry| match entry.title.as_deref().map(str::trim) {
+            Some(title) if !title.is_empty() => title.chars().count() >= min_title_length,
+            _ => true,
+        });
+        eprintln!(
+            "Filtered out {} of {} entries with a title shorter than {} characters.",
+            before - entries.len(),
+            before,
+            min_title_length
+        );
+    }
+
+    // --bench-dedup compares importing with and without the window
+    if cli.dedup_window > 0 && !cli.bench_dedup {
+        let before = entries.len();
+        let collapsed =
+            rewrite::collapse_near_duplicates(&mut entries, cli.dedup_window.saturating_mul(1000));
+        eprintln!(
+            "Collapsed {collapsed} of {before} entries visiting the same URL within {} ms of another.",
+            cli.dedup_window
+        );
+    }
+
+    if let Some(SortOrder::Chronological) = cli.sort {
+        entries.sort_by_key(|entry| entry.time);
+        eprintln!("Sorted {} entries chronologically.", entries.len());
+    }
+
+    if cli.decollide_timestamps {
+        let (moved, failed) = rewrite::decollide_timestamps(&mut entries);
+        eprintln!("Moved {moved} entries with colliding timestamps.");
+        if failed > 0 {
+            eprintln!("{failed} entries with colliding timestamps couldn't be moved without leaving their second.");
+        }
+    }
+
+    if cli.health_check {
+        let report = HealthReport::new(entries.iter().map(|entry| &entry.url));
+        eprintln!("{report}");
+        if report.problems() > 0 {
+            eprintln!("Entries with problems can't be imported, consider excluding them with --url-exclude.");
+        }
+    }
+
+    if let Some(threshold) = cli.warn_place_explosion {
+        let explosions =
+            health::place_explosions(entries.iter().map(|entry| &entry.url), threshold);
+        for (origin, urls) in &explosions {
+            eprintln!("Warning: {origin} has {urls} distinct URLs, which will all become separate places.");
+        }
+        if !explosions.is_empty() {
+            eprintln!("If they only differ in tracking parameters, consider merging them with --merge-queries.");
+        }
+    }
+
+    if let Some(path) = &cli.export_html {
+        let file = BufWriter::new(File::create(path)?);
+        export::write_bookmarks_html(file, &entries)?;
+    }
+
+    if let Some(path) = &cli.export_csv {
+        let file = BufWriter::new(File::create(path)?);
+        csv::write_history(file, &entries)?;
+    }
+
+    let Some(sqlite_db) = &cli.sqlite_db else {
+        if cli.verbose {
+            eprintln!("Parsing: {:.2?}", parsing);
+        }
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let before = entries.len();
+    let mut invalid: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut unsupported = UnsupportedReport::default();
+    entries = entries
+        .into_iter()
+        .filter_map(|entry| {
+            // validate consumes the entry, so keep its URL for the report
+            let url = cli.report_unsupported.is_some().then(|| entry.url.clone());
+            match entry.validate() {
+                Ok(entry) => Some(entry),
+                Err(error) => {
+                    *invalid.entry(error.reason()).or_default() += 1;
+                    if let Some(url) = url {
+                        unsupported.add_error(&url, &error);
+                    }
+                    None
+                }
+            }
+        })
+        .collect();
+    if entries.len() < before {
+        eprintln!(
+            "Skipped {} of {} entries which can't be imported:",
+            before - entries.len(),
+            before
+        );
+        for (reason, count) in &invalid {
+            eprintln!("  {count} with {reason}");
+        }
+    }
+    if cli.strict && entries.len() < before {
+        anyhow::bail!(
+            "--strict doesn't allow skipping {} entries which can't be imported.",
+            before - entries.len()
+        );
+    }
+    if let Some(path) = &cli.report_unsupported {
+        for entry in &entries {
+            unsupported.add_if_opaque(&entry.url);
+        }
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &unsupported)
+            .with_context(|| format!("Failed to write {}.", path.display()))?;
+        eprintln!(
+            "Wrote a report of {} entries which won't be imported to {}.",
+            unsupported.total,
+            path.display()
+        );
+    }
+
+    rewrite::unify_titles(&mut entries, cli.title_from);
+
+    let schema_map = match &cli.schema_map {
+        Some(path) => SchemaMap::from_file(path)?,
+        None => SchemaMap::default(),
+    };
+
+    if cli.verify_hashes && sqlite_db.exists() {
+        let connection = rusqlite::Connection::open_with_flags(
+            sqlite_db,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .with_context(|| format!("Failed to open {}.", sqlite_db.display()))?;
+        #[cfg(feature = "sqlcipher")]
+        if let Some(key) = &cli.key {
+            connection.pragma_update(None, "key", key)?;
+        }
+        let mismatches = diff::verify_hashes(&connection, &entries, &schema_map)?;
+        for mismatch in &mismatches {
+            eprintln!(
+                "Hash mismatch: {} is stored with url_hash {}, but hashes to {}.",
+                mismatch.url, mismatch.stored, mismatch.computed
+            );
+        }
+        if mismatches.is_empty() {
+            eprintln!("The url_hash of all existing places matches their URL.");
+        } else {
+            eprintln!(
+                "{} existing places have a url_hash which doesn't match their URL, \
+                 the hash function may not match this Firefox version.",
+                mismatches.len()
+            );
+        }
+    }
+
+    for path in &cli.dedup_against {
+        let connection =
+            rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("Failed to open {}.", path.display()))?;
+        let exists = diff::exists_in(&connection, &entries, &schema_map)
+            .with_context(|| format!("Failed to read {}.", path.display()))?;
+        let before = entries.len();
+        let mut exists = exists.into_iter();
+        entries.retain(|_| !exists.next().unwrap_or(false));
+        eprintln!(
+            "Skipped {} of {} entries which already exist in {}.",
+            before - entries.len(),
+            before,
+            path.display()
+        );
+    }
+
+    if cli.bench_dedup {
+        let connection = rusqlite::Connection::open_with_flags(
+            sqlite_db,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .with_context(|| format!("Failed to open {}.", sqlite_db.display()))?;
+        #[cfg(feature = "sqlcipher")]
+        if let Some(key) = &cli.key {
+            connection.pragma_update(None, "key", key)?;
+        }
+        let window = if cli.dedup_window > 0 {
+            cli.dedup_window
+        } else {
+            BENCH_DEDUP_WINDOW
+        };
+        let results = bench_dedup(
+            &connection,
+            &entries,
+            window,
+            !cli.no_touch_existing,
+            &schema_map,
+        )?;
+        print_dedup_bench(&results, cli.format)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.dry_run {
+        let connection = rusqlite::Connection::open_with_flags(
+            sqlite_db,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .with_context(|| format!("Failed to open {}.", sqlite_db.display()))?;
+        #[cfg(feature = "sqlcipher")]
+        if let Some(key) = &cli.key {
+            connection.pragma_update(None, "key", key)?;
+        }
+        let diff = diff::diff(
+            &connection,
+            &entries,
+            cli.delta,
+            !cli.no_touch_existing,
+            &schema_map,
+        )?;
+        print_diff(&diff, cli.format)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.single_transaction
+        && !cli.yes
+        && !confirm("The whole import runs in a single transaction, so an interruption loses all progress. Continue?")?
+    {
+        return Ok(ExitCode::from(EXIT_NOTHING_INSERTED));
+    }
+    if cli.compact_origins
+        && !cli.yes
+        && !confirm("--compact-origins merges origins across the whole database, not only the imported ones. Continue?")?
+    {
+        return Ok(ExitCode::from(EXIT_NOTHING_INSERTED));
+    }
+
+    let journal_mode = cli.journal_mode.unwrap_or(if cli.single_transaction {
+        JournalMode::Delete
+    } else {
+        JournalMode::Wal
+    });
+    if let Some(output_db) = &cli.output_db {
+        if output_db.exists() {
+            anyhow::bail!(
+                "{} already exists, pass a path for a new database to --output-db.",
+                output_db.display()
+            );
+        }
+        if sqlite_db.exists() {
+            safe_write::copy_database(sqlite_db, output_db)?;
+        } else if !cli.init_schema {
+            anyhow::bail!(
+                "{} doesn't exist, pass --init-schema to create a new database at {}.",
+                sqlite_db.display(),
+                output_db.display()
+            );
+        }
+    }
+    let safe_write = if cli.safe_write {
+        Some(SafeWrite::prepare(sqlite_db)?)
+    } else {
+        None
+    };
+    let builder = FirefoxHistoryBuilder::new();
+    #[cfg(feature = "sqlcipher")]
+    let builder = match &cli.key {
+        Some(key) => builder.key(key.clone()),
+        None => builder,
+    };
+    let builder = match &cli.tag {
+        Some(label) => builder.tag(label),
+        None => builder,
+    };
+    let builder = match cli.wal_checkpoint_every {
+        Some(batches) => builder.wal_checkpoint_every(batches),
+        None => builder,
+    };
+    let builder = builder
+        .journal_mode(journal_mode)
+        .synchronous(cli.synchronous)
+        .busy_timeout(Duration::from_millis(cli.busy_timeout))
+        .create_schema(cli.init_schema)
+        .deterministic_guids(cli.deterministic_guids)
+        .guid_conflict(cli.on_conflict_guid)
+        .schema_map(schema_map)
+        .options(ImportOptions {
+            duplicate_policy: cli.duplicate_policy,
+            touch_existing_places: !cli.no_touch_existing,
+            visit_source: cli.visit_source,
+            empty_title_mode: cli.empty_title_mode,
+            delta: cli.delta,
+            replace_title_if_better: cli.replace_title_if_better,
+            metadata: cli.metadata,
+            strict: cli.strict,
+        });
+    let path = cli
+        .output_db
+        .as_deref()
+        .or(safe_write.as_ref().map(SafeWrite::path))
+        .unwrap_or(sqlite_db);
+    let mut history = builder.clone().open(path)?;
+    let checker = match cli.parallel_checks {
+        Some(_) if journal_mode != JournalMode::Wal => {
+            anyhow::bail!("--parallel-checks needs the wal journal mode.")
+        }
+        Some(connections) => Some(builder.open_existence_checker(path, connections as usize)?),
+        None => None,
+    };
+
+    if cli.metadata && !history.has_metadata() {
+        eprintln!("The database has no moz_places_metadata table, skipping page interactions.");
+    }
 
-            if let Err(error) = result {
+    if let Some(path) = &cli.emit_sql {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}.", path.display()))?;
+        history.emit_sql(BufWriter::new(file));
+    }
+
+    if cli.skip_bookmarked {
+        match history.bookmarked_urls()? {
+            Some(bookmarks) => {
+                let before = entries.len();
+                entries.retain(|entry| !bookmarks.contains(entry.url.as_str()));
+                eprintln!(
+                    "Skipped {} of {} entries whose URL is bookmarked in the database.",
+                    before - entries.len(),
+                    before
+                );
+            }
+            None => eprintln!("The database has no moz_bookmarks table, nothing is bookmarked."),
+        }
+    }
+
+    if cli.skip_imported {
+        match history.tagged_visits()? {
+            Some(imported) => {
+                let before = entries.len();
+                entries.retain(|entry| {
+                    !imported
+                        .get(entry.url.as_str())
+                        .is_some_and(|times| times.contains(&entry.time))
+                });
                 eprintln!(
-                    "Failed to convert history entry!\n{error}\nEntry: {:#?}",
-                    entry
+                    "Skipped {} of {} entries which tagged imports created before.",
+                    before - entries.len(),
+                    before
                 );
             }
-            progress.inc(1);
+            None => eprintln!(
+                "The database has no tagged imports, all entries are checked for duplicates by their content."
+            ),
         }
-        batch.commit()?;
     }
 
+    let progress = ProgressBar::new(entries.len() as u64);
+
+    let mut batch_size = if cli.single_transaction {
+        entries.len().max(1)
+    } else {
+        1000
+    };
+    let mut processed = 0;
+    if let Some(state) = &resume {
+        for mismatch in state.mismatches(entries.len(), batch_size, cli.entry_offset.unwrap_or(0)) {
+            eprintln!("Warning: {mismatch}, resuming with its batches anyway.");
+        }
+        batch_size = state.batch_size;
+        processed = state.processed.min(entries.len());
+        eprintln!(
+            "Resuming after {processed} of {} entries, which were imported before.",
+            entries.len()
+        );
+        progress.inc(processed as u64);
+    }
+    let resumed = processed;
+    let mut failed = 0;
+    let import_start = Instant::now();
+    let mut checks_waited = Duration::ZERO;
+    let mut batch_sizes = Vec::new();
+    std::thread::scope(|scope| -> anyhow::Result<()> {
+        // the checks of the next batch run while the current one is written
+        let checked = checker.map(|mut checker| {
+            let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+            let entries = &entries;
+            scope.spawn(move || {
+                for chunk in entries[resumed..].chunks(batch_size) {
+                    if sender.send(checker.check(chunk)).is_err() {
+                        break;
+                    }
+                }
+            });
+            receiver
+        });
+        // results of the checks, which come in chunks of batch_size entries
+        let mut exists = VecDeque::new();
+        while processed < entries.len() {
+            let batch_start = Instant::now();
+            let mut batch = history.begin()?;
+            let mut size = 0;
+            for entry in &entries[processed..] {
+                if size > 0
+                    && (size == batch_size
+                        || cli
+                            .commit_interval
+                            .is_some_and(|interval| batch_start.elapsed() >= interval))
+                {
+                    break;
+                }
+                let result = match &checked {
+                    Some(checked) => {
+                        if exists.is_empty() {
+                            let start = Instant::now();
+                            exists.extend(
+                                checked
+                                    .recv()
+                                    .context("The existence checks stopped unexpectedly.")??,
+                            );
+                            checks_waited += start.elapsed();
+                        }
+                        let exists = exists.pop_front().expect("The checks skipped an entry.");
+                        batch.insert_checked_visit(entry, exists)
+                    }
+                    None => batch.insert_visit(entry),
+                };
+
+                if let Err(error) = result {
+                    if cli.strict {
+                        return Err(error.context(format!(
+                            "Failed to import {}, aborting because of --strict.",
+                            entry.url
+                        )));
+                    }
+                    failed += 1;
+                    eprintln!(
+                        "Failed to convert history entry!\n{error}\nEntry: {:#?}",
+                        entry
+                    );
+                }
+                progress.inc(1);
+                size += 1;
+            }
+            batch.commit()?;
+            processed += size;
+            batch_sizes.push(size);
+            if let Some(path) = &cli.state_file {
+                ResumeState {
+                    entries: entries.len(),
+                    processed,
+                    batch_size,
+                    entry_offset: entry_offset.unwrap_or(0),
+                }
+                .save(path)?;
+            }
+
+            if let Some(every) = cli.checkpoint_every {
+                if (batch_sizes.len() as u64).is_multiple_of(every) && processed < entries.len() {
+                    let elapsed = import_start.elapsed();
+                    let rate = processed as f64 / elapsed.as_secs_f64();
+                    let eta = Duration::from_secs_f64((entries.len() - processed) as f64 / rate);
+                    // the progress bar is hidden without a terminal, but this should show up in logs too
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Processed {} of {} entries in {:.0?}, {} inserted and {} failed so far, \
+                            {:.0} entries/s, about {:.0?} left.",
+                            processed,
+                            entries.len(),
+                            elapsed,
+                            history.counts().inserted,
+                            failed,
+                            rate,
+                            eta
+                        )
+                    });
+                }
+            }
+
+            if let Some(throttle) = cli.throttle {
+                std::thread::sleep(Duration::from_millis(throttle));
+            }
+        }
+        Ok(())
+    })?;
+
     progress.finish_and_clear();
+    if let Some(path) = &cli.state_file {
+        // the import is complete, there's nothing left to resume
+        std::fs::remove_file(path)
+            .or_else(|error| match error.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(error),
+            })
+            .with_context(|| format!("Failed to remove {}.", path.display()))?;
+    }
 
-    Ok(())
-}
+    if cli.reconcile_counts {
+        let reconciled = history.reconcile_visit_counts()?;
+        eprintln!("Reconciled the visit_count of {reconciled} places with their visits.");
+    }
+    if cli.repair {
+        let repaired = history.repair_visit_counts()?;
+        eprintln!("Repaired the visit_count of {repaired} places.");
+    } else if cli.duplicate_policy != DuplicatePolicy::Count {
+        // counting duplicates raises visit_count above the visits on purpose, that's no mismatch to warn about
+        let mismatched = history.count_mismatched_visit_counts()?;
+        if mismatched > 0 {
+            eprintln!("{mismatched} places have a visit_count which doesn't match their visits, pass --repair to fix them.");
+        }
+    }
 
-#[derive(serde::Deserialize)]
-struct ChromeTakeoutFile {
-    #[serde(rename = "Browser History")]
-    history: Box<[ChromeTakeoutEntry]>,
-}
+    if cli.compact_origins {
+        let merged = history.compact_origins()?;
+        eprintln!("Merged {merged} origins into equivalent ones.");
+    }
+
+    if cli.simulate_firefox_recalc {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+        let places = history.recalculate_frecency(now)?;
+        eprintln!("Calculated the frecency of {places} places.");
+    }
+
+    if cli.emit_sql.is_some() {
+        history.finish_sql()?;
+    }
+
+    if cli.verbose {
+        let timings = history.timings();
+        eprintln!("Parsing: {:.2?}", parsing);
+        eprintln!("Existence checks: {:.2?}", timings.existence_checks);
+        if cli.parallel_checks.is_some() {
+            eprintln!(
+                "Waiting for parallel existence checks: {:.2?}",
+                checks_waited
+            );
+        }
+        eprintln!(
+            "Place and origin resolution: {:.2?}",
+            timings.place_resolution
+        );
+        eprintln!("Inserts: {:.2?}", timings.inserts);
+        eprintln!("Commits: {:.2?}", timings.commits);
+        if cli.wal_checkpoint_every.is_some() {
+            let wal = history.wal_stats();
+            eprintln!(
+                "WAL checkpoints: {}, in {:.2?}, with at most {} pages in the log",
+                wal.checkpoints, timings.wal_checkpoints, wal.max_frames
+            );
+        }
+        if let (Some(min), Some(max)) = (batch_sizes.iter().min(), batch_sizes.iter().max()) {
+            eprintln!(
+                "Batches: {}, with {} to {} entries, {:.0} on average",
+                batch_sizes.len(),
+                min,
+                max,
+                processed as f64 / batch_sizes.len() as f64
+            );
+        }
+    }
+
+    let summary = Summary {
+        entries: entries.len(),
+        counts: history.counts(),
+        failed,
+    };
+    if let Some(safe_write) = safe_write {
+        drop(history);
+        safe_write.finish()?;
+    }
+    if !cli.no_manifest {
+        let record = ImportRecord::new(
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64,
+            history_path,
+            summary.entries,
+            summary.counts,
+            summary.failed,
+            cli.tag.clone(),
+        )?;
+        manifest::append(
+            &manifest::path(cli.output_db.as_deref().unwrap_or(sqlite_db)),
+            &record,
+        )?;
+    }
+    if let Some(path) = &cli.html_report {
+        let file = BufWriter::new(File::create(path)?);
+        report::write_html_report(
+            file,
+            &ImportStats::new(&entries, cli.tz),
+            summary.entries,
+            summary.counts,
+            summary.failed,
+        )
+        .with_context(|| format!("Failed to write {}.", path.display()))?;
+    }
+    match cli.format {
+        OutputFormat::Text => eprintln!(
+            "Imported {} of {} entries, {} already existed, {} were only counted, {} had an opaque URL and {} failed.",
+            summary.counts.inserted,
+            summary.entries,
+            summary.counts.skipped,
+            summary.counts.counted,
+            summary.counts.opaque,
+            summary.failed
+        ),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&summary)?),
+        OutputFormat::None => {}
+    }
 
-#[derive(serde::Deserialize, Debug)]
-struct ChromeTakeoutEntry {
-    title: String,
-    url: Url,
-    time_usec: u64,
+    Ok(if summary.failed > 0 {
+        ExitCode::from(EXIT_PARTIAL_FAILURE)
+    } else if summary.entries > 0 && summary.counts.inserted == 0 {
+        ExitCode::from(EXIT_NOTHING_INSERTED)
+    } else {
+        ExitCode::SUCCESS
+    })
 }
 
+/// Listed in `--help`, keep in sync with the `EXIT_*` constants and the README.
+const EXIT_CODES: &str = "Exit codes:
+  0  Success, visits were imported, or there were no entries to import, e.g. all were filtered out
+  1  Error, the database may be partially written unless --single-transaction or --safe-write was used
+  2  Invalid arguments
+  3  Success, but none of the entries added a visit, e.g. because they all existed already,
+     or a confirmation was declined
+  4  The import finished, but some entries failed to convert";
+
 #[derive(clap::Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = EXIT_CODES
+)]
 struct Cli {
-    /// Path to the chrome takeout history json file.
-    #[arg(name = "chrome-takeout-history-path")]
-    chrome_takeout_history_path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to the chrome takeout history json file, a dump of Chrome's sync data,
+    /// history exported from Firefox by an add-on, or a CSV file with `--import-csv`.
+    /// Use `-` to read from stdin.
+    #[arg(name = "chrome-takeout-history-path", required = true)]
+    chrome_takeout_history_path: Option<PathBuf>,
     /// Firefox places.sqlite to operate on.
-    #[arg(name = "sqlite-db")]
-    sqlite_db: PathBuf,
+    #[arg(
+        name = "sqlite-db",
+        required_unless_present_any = ["export_html", "export_csv", "health_check", "preview"]
+    )]
+    sqlite_db: Option<PathBuf>,
+    /// Also write the history as a Netscape bookmark HTML file,
+    /// which can be imported using Firefox's own import dialog.
+    #[arg(long, value_name = "PATH")]
+    export_html: Option<PathBuf>,
+    /// Also write the history as a CSV file with the columns url, title and visit_date.
+    #[arg(long, value_name = "PATH")]
+    export_csv: Option<PathBuf>,
+    /// Layout of the JSON input, detected by its top-level keys if not given.
+    #[arg(long, value_enum, conflicts_with = "import_csv")]
+    source: Option<takeout::Source>,
+    /// TOML file which maps Chrome's page transitions to Firefox visit types, on top of the built-in mapping.
+    /// Takeouts, Chrome sync data and Firefox exports have transitions, Safari and CSV input don't. See the `transition` module of the library for the format.
+    #[arg(long, value_name = "PATH", conflicts_with = "import_csv")]
+    transition_map: Option<PathBuf>,
+    /// Read the input as a CSV file with the columns url, title and visit_date
+    /// instead of a chrome takeout.
+    #[arg(long)]
+    import_csv: bool,
+    /// Map the input file into memory instead of reading it into a buffer,
+    /// which is faster for huge files. Falls back to reading for stdin and files which can't be mapped.
+    #[arg(long)]
+    mmap: bool,
+    /// Skip the first N entries of the input, e.g. to resume an import which was split up by hand
+    /// or to leave out a broken beginning.
+    #[arg(long, value_name = "N")]
+    entry_offset: Option<usize>,
+    /// Also import the pages which redirected to the visited ones, for sources which record them,
+    /// like the `history` of Chrome's sync data. They are linked to the pages they redirected to,
+    /// as redirects Firefox itself recorded are.
+    #[arg(long)]
+    redirects: bool,
+    /// Also import the searches from a Google My Activity `MyActivity.json`,
+    /// found in `My Activity/Search` of a takeout, as typed visits to the search results.
+    /// Together with `--sort chronological`, visits to results are linked to the search leading to them.
+    #[arg(long, value_name = "PATH")]
+    include_searches: Option<PathBuf>,
+    /// Move the time of every entry by a duration with a sign, e.g. `-1h` or `+30m`,
+    /// to correct a clock which was off or times which were stored in the wrong time zone.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = time::parse_shift,
+        allow_hyphen_values = true
+    )]
+    time_shift: Option<i64>,
+    /// Only import a random sample of the entries, e.g. `0.1` for about 10% of them,
+    /// to try out or benchmark an import without processing everything.
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction)]
+    sample: Option<f64>,
+    /// Seed of the random number generator of `--sample`. The same seed picks the same entries of the same input.
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "sample")]
+    sample_seed: u64,
+    /// Rewrite http:// URLs to https:// before importing them.
+    /// Optionally takes a comma separated list of domains to limit the rewrite to.
+    #[arg(
+        long,
+        value_name = "DOMAINS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    upgrade_http: Option<Vec<String>>,
+    /// Whether URLs which only differ in their `#fragment`, like anchors on documentation pages,
+    /// are imported as separate pages.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = FragmentMode::Keep)]
+    fragments: FragmentMode,
+    /// Remove query parameters, so URLs which only differ in them become the same page.
+    /// Optionally takes a comma separated list of parameters to keep, e.g. `--merge-queries=q,id`.
+    #[arg(
+        long,
+        value_name = "PARAMS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    merge_queries: Option<Vec<String>>,
+    /// Only import URLs matching this regular expression. Can be passed multiple times.
+    #[arg(long, value_name = "REGEX")]
+    url_filter: Vec<Regex>,
+    /// Don't import URLs matching this regular expression. Can be passed multiple times.
+    #[arg(long, value_name = "REGEX")]
+    url_exclude: Vec<Regex>,
+    /// Don't import noise, which are visits to browser internal pages like `chrome-extension://`,
+    /// pages titled e.g. "New Tab" or "Loading…", and visits shorter than a second, if the input has durations.
+    #[arg(long)]
+    exclude_noise: bool,
+    /// Schemes whose visits are noise, replacing the default ones. Comma separated.
+    #[arg(
+        long,
+        value_name = "SCHEMES",
+        value_delimiter = ',',
+        requires = "exclude_noise"
+    )]
+    noise_scheme: Option<Vec<String>>,
+    /// Regular expression matching titles of noise, replacing the default ones. Can be passed multiple times.
+    #[arg(long, value_name = "REGEX", requires = "exclude_noise")]
+    noise_title: Option<Vec<Regex>>,
+    /// Visits shorter than this many milliseconds are noise. 0 disables this rule.
+    #[arg(long, value_name = "MS", requires = "exclude_noise")]
+    noise_min_duration: Option<u64>,
+    /// Only import URLs which are bookmarked in this Netscape bookmark file,
+    /// like the Bookmarks.html of a chrome takeout.
+    #[arg(long, value_name = "PATH")]
+    only_bookmarked: Option<PathBuf>,
+    /// Skip URLs which are already bookmarked in the database, to only import browsing outside of them.
+    #[arg(long)]
+    skip_bookmarked: bool,
+    /// Skip entries which already exist in another places.sqlite, by their URL and time,
+    /// e.g. to only import the history a profile doesn't have yet when merging profiles.
+    /// It is opened read-only, with `--schema-map` but without `--key`. Can be passed multiple times.
+    #[arg(long, value_name = "PATH")]
+    dedup_against: Vec<PathBuf>,
+    /// Only import URLs which have at least this many visits in the input.
+    #[arg(long, value_name = "N")]
+    min_visit_count: Option<usize>,
+    /// Repair titles whose UTF-8 was decoded as Latin-1 or Windows-1252 by an export, like `CafÃ©` for `Café`,
+    /// before rewriting them. Only titles which decode to valid UTF-8 that way are changed, correct ones stay as they are.
+    #[arg(long)]
+    fix_mojibake: bool,
+    /// Replace matches of a regular expression in titles.
+    /// `$1` in the replacement inserts the first group. Can be passed multiple times.
+    #[arg(long, num_args = 2, value_names = ["REGEX", "REPLACEMENT"])]
+    title_rewrite: Vec<String>,
+    /// Rhai script which can rewrite the url, title and time of every entry, or skip it.
+    /// See the `transform` module of the library for the variables the script gets.
+    #[cfg(feature = "transform")]
+    #[arg(long, value_name = "SCRIPT")]
+    transform: Option<PathBuf>,
+    /// Skip entries whose title is shorter than N characters, after rewriting titles.
+    /// Entries without a title are kept.
+    #[arg(long, value_name = "N")]
+    min_title_length: Option<usize>,
+    /// Collapse visits of the same URL which are at most this many milliseconds apart into the earliest one,
+    /// for exports which record a visit several times. 0 only treats visits at the exact same time as duplicates.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    dedup_window: u64,
+    /// Reorder the entries before importing them, instead of using the order of the input file.
+    #[arg(long, value_enum, value_name = "ORDER")]
+    sort: Option<SortOrder>,
+    /// Which title a page gets if its entries have different titles.
+    /// Entries without a title are ignored.
+    #[arg(long, value_enum, value_name = "SOURCE", default_value_t = TitleSource::First)]
+    title_from: TitleSource,
+    /// Move entries with identical timestamps apart by a microsecond,
+    /// so they aren't skipped as duplicates of each other.
+    #[arg(long)]
+    decollide_timestamps: bool,
+    /// What to do with visits whose timestamp already exists in the database.
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::Skip)]
+    duplicate_policy: DuplicatePolicy,
+    /// Only skip visits whose URL already has a visit at the same time,
+    /// instead of any visit at the same time. Useful when importing an updated takeout again.
+    #[arg(long, conflicts_with = "duplicate_policy")]
+    delta: bool,
+    /// Only add visits to places which already exist in the database,
+    /// without updating their visit_count and last_visit_date.
+    /// Firefox won't rank those places higher until it recalculates their frecency by itself.
+    #[arg(long)]
+    no_touch_existing: bool,
+    /// Value to store in the source column of imported visits, e.g. to tell them apart later.
+    /// Firefox uses 0 for organic, 1 for synced, 2 for bookmarked and 3 for searched visits.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    visit_source: u32,
+    /// Also update the titles of pages which are already in the database,
+    /// if the new title isn't empty or the URL and is longer than the existing one.
+    #[arg(long)]
+    replace_title_if_better: bool,
+    /// Also add a page interaction to moz_places_metadata for every imported visit,
+    /// which newer Firefox versions use for features like history grouping.
+    /// Skipped if the database doesn't have that table.
+    #[arg(long)]
+    metadata: bool,
+    /// How to store entries without a title.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = EmptyTitleMode::Null)]
+    empty_title_mode: EmptyTitleMode,
+    /// Journal mode of the database.
+    /// Defaults to wal, or delete with `--single-transaction`.
+    #[arg(long, value_enum)]
+    journal_mode: Option<JournalMode>,
+    /// Synchronous level of the database.
+    #[arg(long, value_enum, default_value_t = Synchronous::Normal)]
+    synchronous: Synchronous,
+    /// How many milliseconds to wait for the database to be unlocked.
+    #[arg(long, value_name = "MS", default_value_t = 5000)]
+    busy_timeout: u64,
+    /// Don't record this import in the manifest next to the database, which the `history` subcommand lists.
+    #[arg(long)]
+    no_manifest: bool,
+    /// Record the visits and places this import creates under LABEL in the database,
+    /// so they can be removed again with the `remove-tag` subcommand.
+    #[arg(long, value_name = "LABEL")]
+    tag: Option<String>,
+    /// Skip entries whose visit an earlier import tagged with `--tag` created, under any label,
+    /// without checking the database for them one by one. This makes recurring imports of a growing history fast.
+    /// Entries which no tagged import created are still checked for duplicates as usual.
+    /// An interrupted import can be repeated with this instead of being resumed.
+    #[arg(long, conflicts_with = "state_file")]
+    skip_imported: bool,
+    /// Import into a copy of the database, which replaces the original only if the import succeeded,
+    /// so the original is never left half written. Needs free disk space for the copy.
+    #[arg(long)]
+    safe_write: bool,
+    /// Import into a new database at PATH instead, leaving the one given as SQLITE_DB untouched.
+    /// It starts as a copy of SQLITE_DB, or, if that doesn't exist and --init-schema is passed, empty.
+    #[arg(long, value_name = "PATH", conflicts_with = "safe_write")]
+    output_db: Option<PathBuf>,
+    /// Also write the statements which change the database to this file, with their values inlined,
+    /// to review them or apply them with other SQLite tools.
+    /// Pair it with --output-db to get them without changing the original database.
+    #[arg(long, value_name = "PATH")]
+    emit_sql: Option<PathBuf>,
+    /// Sleep this many milliseconds after each batch of 1000 entries,
+    /// to leave some disk IO for other processes.
+    #[arg(long, value_name = "MS")]
+    throttle: Option<u64>,
+    /// Commit a batch once it took this long, e.g. `2s` or `500ms`, even if it has fewer than 1000 entries.
+    /// This bounds how long the database stays locked for other processes when entries are slow to import.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = time::parse_duration,
+        conflicts_with = "single_transaction"
+    )]
+    commit_interval: Option<Duration>,
+    /// Print how far the import got every N committed batches,
+    /// for long imports without a terminal to show the progress bar, e.g. with the output redirected to a log.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_every: Option<u64>,
+    /// Checkpoint the write-ahead log every N committed batches. SQLite checkpoints on its own once the log
+    /// holds 1000 pages after a commit, so it grows to that plus a batch, with 1 it stays at about one batch.
+    /// With `--verbose`, how many pages it held at most is printed. Only has an effect with the wal journal mode.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    wal_checkpoint_every: Option<u64>,
+    /// Check which entries already exist on N extra read-only connections,
+    /// in parallel with each other and while the previous batch is written. Needs the wal journal mode.
+    /// This only pays off with spare CPU cores and slow storage, otherwise the extra connections cost more than they save.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "single_transaction"
+    )]
+    parallel_checks: Option<u64>,
+    /// Keep track of the committed entries in this file, and resume after them if it exists,
+    /// e.g. after the import was interrupted. The batches stay the same as if it never was.
+    /// The file is removed once the import is complete.
+    #[arg(long, value_name = "PATH", conflicts_with = "single_transaction")]
+    state_file: Option<PathBuf>,
+    /// Import everything in a single transaction, which is committed at the end.
+    /// Nothing is written if the import is interrupted, but all progress is lost.
+    #[arg(long)]
+    single_transaction: bool,
+    /// Don't write anything, instead list which pages are new to the database,
+    /// how the visit_count of existing ones would change and how many visits already exist.
+    /// Printed as a table or, with `--format json`, as JSON.
+    #[arg(long, conflicts_with = "duplicate_policy")]
+    dry_run: bool,
+    /// Don't write anything, instead compare how many visits each way of telling duplicates apart would import,
+    /// and how long it takes: any visit at the same time, `--delta`, and `--delta` after `--dedup-window`,
+    /// with the given window or 1000 ms. Combine it with `--sample` for big histories.
+    #[arg(long, conflicts_with_all = ["dry_run", "duplicate_policy"])]
+    bench_dedup: bool,
+    /// Abort instead of skipping any entry, so nothing is left out silently.
+    /// Skipped are entries which already exist, unless `--duplicate-policy` is `replace` or `count`,
+    /// entries whose URL has an unsupported scheme, no host, an opaque origin or is too long,
+    /// entries with implausible times, and entries which fail to convert.
+    /// Entries left out by filters like `--url-exclude` aren't skipped, they were asked to be left out.
+    /// Batches committed before the abort stay, unless `--single-transaction` or `--safe-write` is passed too.
+    #[arg(long)]
+    strict: bool,
+    /// Don't ask for confirmation.
+    #[arg(long, short)]
+    yes: bool,
+    /// After importing, fix the visit_count of places which doesn't match their visits,
+    /// e.g. after an interrupted import. Without this they are only reported.
+    #[arg(long)]
+    repair: bool,
+    /// After importing, recount the visits of the places the import changed, in case the counting while importing drifted,
+    /// e.g. because of reloads, which Firefox doesn't count. This undoes `--duplicate-policy count`.
+    #[arg(long)]
+    reconcile_counts: bool,
+    /// After importing, merge origins which only differ in case or a default port, e.g. `HTTPS://Example.com:443`,
+    /// which split the frecency of a site. This changes the whole database, so it asks for confirmation.
+    #[arg(long)]
+    compact_origins: bool,
+    /// After importing, calculate the frecency of changed places like Firefox would in the background,
+    /// so they are ranked in the address bar right away. This can take a while for big histories.
+    #[arg(long)]
+    simulate_firefox_recalc: bool,
+    /// Key of a SQLCipher encrypted database, as used by some hardened Firefox forks.
+    /// Note that the key is visible to other users in the process list.
+    #[cfg(feature = "sqlcipher")]
+    #[arg(long)]
+    key: Option<String>,
+    /// Create the history tables if they don't exist.
+    /// This doesn't create a complete Firefox profile, it's only meant for testing.
+    #[arg(long)]
+    init_schema: bool,
+    /// TOML file which maps the names of Firefox's tables to the ones of the database,
+    /// for forks or Firefox versions which renamed them. See the `schema_map` module of the library for the format.
+    #[arg(long, value_name = "PATH")]
+    schema_map: Option<PathBuf>,
+    /// Derive the guids of new pages from their URL instead of random bytes,
+    /// so importing the same history into copies of the same database gives identical results.
+    /// Guids which are already taken are still retried with another derived guid.
+    #[arg(long)]
+    deterministic_guids: bool,
+    /// What to do when the guid picked for a new page is already taken.
+    /// Regenerating is strongly preferred, failing is only meant to find out whether conflicts happen at all.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = GuidConflict::Regenerate)]
+    on_conflict_guid: GuidConflict,
+    /// Write a JSON report of the entries which won't be imported to PATH, grouped by why:
+    /// by scheme for unsupported schemes, URLs without a host and opaque origins,
+    /// by host for URLs which are too long and by year for implausible times.
+    #[arg(long, value_name = "PATH")]
+    report_unsupported: Option<PathBuf>,
+    /// Before importing, check the url_hash of the pages which already exist in the database against the one
+    /// this tool computes, and list the ones which differ. Mismatches hint at a bug in the hash function,
+    /// or a Firefox version which hashes differently, and would break the lookup of new pages.
+    #[arg(long)]
+    verify_hashes: bool,
+    /// Warn about origins with more than N distinct URLs, which often only differ in tracking parameters.
+    #[arg(long, value_name = "N")]
+    warn_place_explosion: Option<usize>,
+    /// Print the first N parsed entries with their times and exit without importing anything.
+    #[arg(long, value_name = "N")]
+    preview: Option<usize>,
+    /// IANA time zone to print times in, e.g. `America/New_York`. Only changes how times are displayed.
+    #[arg(long, value_name = "ZONE", default_value_t = chrono_tz::UTC)]
+    tz: chrono_tz::Tz,
+    /// Report how many entries have URLs which can't be imported before importing anything.
+    #[arg(long)]
+    health_check: bool,
+    /// How to print the summary at the end of an import, or the result of `--dry-run`.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// After importing, write a recap of the import to PATH as a standalone HTML page,
+    /// with charts of the visits per day, in the time zone of `--tz`, and of the domains with the most visits.
+    /// It loads nothing from elsewhere, so it opens offline.
+    #[arg(long, value_name = "PATH")]
+    html_report: Option<PathBuf>,
+    /// Print how much time was spent in each phase of the import.
+    #[arg(long)]
+    verbose: bool,
 }
 
-struct FirefoxHistory {
-    connection: rusqlite::Connection,
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    /// A sentence on stderr.
+    Text,
+    /// A JSON object on stdout.
+    Json,
+    /// Nothing.
+    None,
 }
 
-impl FirefoxHistory {
-    pub fn open_file(path: &Path) -> anyhow::Result<Self> {
-        let connection = rusqlite::Connection::open(path)?;
-        connection.pragma_update(None, "journal_mode", "wal")?;
-        connection.pragma_update(None, "synchronous", "NORMAL")?;
-        Ok(Self { connection })
+/// Outcome of an import, printed according to `--format`.
+#[derive(serde::Serialize)]
+struct Summary {
+    entries: usize,
+    #[serde(flatten)]
+    counts: Counts,
+    failed: u64,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SortOrder {
+    /// Oldest entries first. Takeouts are usually newest first.
+    Chronological,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// List the Firefox profiles found in profiles.ini,
+    /// and whether their places.sqlite can be imported into.
+    ListProfiles {
+        /// Application whose profiles to list.
+        #[arg(long, value_enum, default_value_t = TargetApp::Firefox)]
+        target_app: TargetApp,
+    },
+    /// Import a small sample takeout into a temporary database and check the result,
+    /// to make sure the tool works on this system before touching a real profile.
+    SelfTest,
+    /// Compare the places and visits of two places.sqlite files, e.g. a backup and the database after an import.
+    /// Both are opened read-only.
+    Diff {
+        first: PathBuf,
+        second: PathBuf,
+        /// How to print the differences.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Remove the visits an import with `--tag LABEL` created, and the places it created which are left without visits.
+    RemoveTag {
+        label: String,
+        /// places.sqlite to remove the visits from.
+        places: PathBuf,
+    },
+    /// List the imports into a places.sqlite, from the manifest every import appends to,
+    /// which is kept next to it as `places.sqlite.imports.jsonl`.
+    History {
+        places: PathBuf,
+        /// How to print the imports.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Print url and url_hash pairs of a places.sqlite written by Firefox
+    /// in the format of the hash tests, to check the hash function against new Firefox versions.
+    #[command(hide = true)]
+    GenerateHashVectors {
+        /// places.sqlite to read the pairs from. It is opened read-only.
+        places: PathBuf,
+        /// How many random places to print.
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        count: usize,
+    },
+}
+
+fn diff_databases(first: &Path, second: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let open = |path: &Path| {
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open {}.", path.display()))
+    };
+    let diff = diff::compare(&open(first)?, &open(second)?)?;
+    match format {
+        OutputFormat::Text => print_database_diff(&diff),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&diff)?),
+        OutputFormat::None => {}
     }
+    Ok(())
+}
 
-    pub fn begin(&mut self) -> anyhow::Result<FirefoxHistoryBatch> {
-        Ok(FirefoxHistoryBatch {
-            transaction: self.connection.transaction()?,
-        })
+fn print_database_diff(diff: &DatabaseDiff) {
+    println!("Places: {} -> {}", diff.places.0, diff.places.1);
+    println!("Visits: {} -> {}", diff.visits.0, diff.visits.1);
+    for url in &diff.only_in_first {
+        println!("- {url}");
+    }
+    for url in &diff.only_in_second {
+        println!("+ {url}");
     }
 }
 
-struct FirefoxHistoryBatch<'a> {
-    transaction: Transaction<'a>,
+fn remove_tag(places: &Path, label: &str) -> anyhow::Result<()> {
+    let mut history = FirefoxHistory::open_file(places)?;
+    let (visits, removed_places) = history.remove_tag(label)?;
+    eprintln!("Removed {visits} visits and {removed_places} places tagged {label:?}.");
+    Ok(())
 }
 
-impl FirefoxHistoryBatch<'_> {
-    pub fn insert_visit(
-        &mut self,
-        url: &Url,
-        title: Option<&str>,
-        time: u64,
-    ) -> anyhow::Result<()> {
-        let exists: bool = {
-            let mut statement = self.transaction.prepare_cached(
-                "SELECT EXISTS(SELECT 1 FROM moz_historyvisits WHERE visit_date = ?1)",
-            )?;
-            statement.query_row([time], |row| row.get(0))?
-        };
+fn show_history(places: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let path = manifest::path(places);
+    let records = manifest::read(&path)?;
+    match format {
+        OutputFormat::Text => {
+            if records.is_empty() {
+                eprintln!("{} has no imports recorded.", places.display());
+                return Ok(());
+            }
+            println!("TIME\tINSERTED\tSKIPPED\tFAILED\tTAG\tSOURCE\tHASH");
+            for record in &records {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    time::format_utc(record.time),
+                    record.inserted,
+                    record.skipped,
+                    record.failed,
+                    record.tag.as_deref().unwrap_or("-"),
+                    record.source,
+                    record.source_hash.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+        OutputFormat::None => {}
+    }
+    Ok(())
+}
 
-        if exists {
-            eprintln!(
-                "Skipping entry because it already exists.\nUrl: {}\nTitle: {:?}\nTime: {}",
-                url, title, time
-            );
-            return Ok(());
+fn generate_hash_vectors(places: &Path, count: usize) -> anyhow::Result<()> {
+    let connection =
+        rusqlite::Connection::open_with_flags(places, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open {}.", places.display()))?;
+    let mut statement =
+        connection.prepare("SELECT url, url_hash FROM moz_places ORDER BY random() LIMIT ?1")?;
+    let mut rows = statement.query([count])?;
+    let mut mismatches = 0;
+    while let Some(row) = rows.next()? {
+        let (url, url_hash): (String, u64) = (row.get(0)?, row.get(1)?);
+        println!("            ({url:?}, {url_hash}),");
+        if hash::hash(&url)? != url_hash {
+            mismatches += 1;
+            eprintln!("Mismatch: {url}");
         }
+    }
+    if mismatches > 0 {
+        anyhow::bail!("{mismatches} url_hash values don't match the hash function.");
+    }
+    Ok(())
+}
 
-        // find the place we want to visit
-        let place = find_or_insert_place(url, title, &mut self.transaction)?;
+/// Sample history imported by the self-test.
+const SELF_TEST_TAKEOUT: &str = include_str!("../tests/fixtures/History.json");
 
-        {
-            let mut statement = self.transaction.prepare_cached(
-                r#"
-                    UPDATE moz_places
-                    SET visit_count = visit_count + 1,
-                        last_visit_date = max(ifnull(last_visit_date, 0), ?1),
-                        recalc_frecency = 1
-                    WHERE id = (?2)
-                "#,
-            )?;
+fn self_test() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!(
+        "chrome-takeout-to-firefox-self-test-{}.sqlite",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let result = run_self_test(&path);
+    let _ = std::fs::remove_file(&path);
 
-            statement.execute((time, place))?;
+    if !result? {
+        anyhow::bail!("The self-test failed.");
+    }
+    println!("The self-test passed.");
+    Ok(())
+}
 
-            let mut statement = self.transaction.prepare_cached(
-                r#"
-            INSERT INTO moz_historyvisits
-                (from_visit, place_id, visit_date, visit_type, session, source, triggeringPlaceId)
-            VALUES
-                (0, ?1, ?2, 1, 0, 0, NULL)
-                "#,
-            )?;
+/// Returns whether all checks passed, or an error if the import itself failed.
+fn run_self_test(path: &Path) -> anyhow::Result<bool> {
+    let entries = takeout::read_history(SELF_TEST_TAKEOUT.as_bytes())?;
+    let mut passed = true;
+    let mut check = |name: &str, ok: bool| {
+        println!("{} {name}", if ok { "PASS" } else { "FAIL" });
+        passed &= ok;
+    };
+    check("parse the sample takeout", entries.len() == 4);
 
-            statement.execute((place, time))?;
-        }
+    let mut history = FirefoxHistoryBuilder::new()
+        .journal_mode(JournalMode::Delete)
+        .create_schema(true)
+        .open(path)?;
+    let mut batch = history.begin()?;
+    let inserted = entries
+        .iter()
+        .map(|entry| batch.insert_visit(entry))
+        .collect::<anyhow::Result<Vec<_>>>();
+    check("import the entries", inserted.is_ok());
+    batch.commit()?;
 
-        Ok(())
-    }
+    let connection = rusqlite::Connection::open(path)?;
+    let count =
+        |sql: &str| -> anyhow::Result<u32> { Ok(connection.query_row(sql, [], |row| row.get(0))?) };
+    check(
+        "create one place per URL",
+        count("SELECT COUNT(*) FROM moz_places")? == 3,
+    );
+    check(
+        "create one visit per entry",
+        count("SELECT COUNT(*) FROM moz_historyvisits")? == 4,
+    );
+    check(
+        "create one origin per host",
+        count("SELECT COUNT(*) FROM moz_origins")? == 3,
+    );
+    check(
+        "link every place to an origin",
+        count(
+            "SELECT COUNT(*) FROM moz_places WHERE origin_id NOT IN (SELECT id FROM moz_origins)",
+        )? == 0,
+    );
+    check(
+        "count the visits of every place",
+        history.count_mismatched_visit_counts()? == 0,
+    );
+    Ok(passed)
+}
 
-    pub fn commit(self) -> anyhow::Result<()> {
-        self.transaction.commit()?;
-        Ok(())
+fn list_profiles(target_app: TargetApp) -> anyhow::Result<()> {
+    let profiles = profiles::discover(target_app)?;
+    if profiles.is_empty() {
+        anyhow::bail!("Couldn't find a profiles.ini of {target_app:?}.");
     }
+    for profile in profiles {
+        println!(
+            "{}{}\t{}\t{}",
+            profile.name,
+            if profile.is_default { " (default)" } else { "" },
+            profile.path.display(),
+            profile.status()
+        );
+    }
+    Ok(())
 }
 
-fn find_or_insert_place(
-    url: &Url,
-    title: Option<&str>,
-    transaction: &mut Transaction,
-) -> anyhow::Result<u32> {
-    let id: Option<u32> = {
-        let mut statement =
-            transaction.prepare_cached("SELECT id FROM moz_places WHERE url = (?1)")?;
-        statement.query_row([&url], |row| row.get(0)).optional()?
-    };
-
-    if let Some(id) = id {
-        return Ok(id);
+fn print_diff(diff: &Diff, format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            println!("STATUS\tVISIT_COUNT\tDUPLICATES\tURL");
+            for page in &diff.pages {
+                println!(
+                    "{}\t{} -> {}\t{}\t{}",
+                    if page.exists { "existing" } else { "new" },
+                    page.visit_count,
+                    page.new_visit_count,
+                    page.duplicate_visits,
+                    page.url
+                );
+            }
+            eprintln!(
+                "{} new and {} existing pages, {} visits would be imported and {} already exist.",
+                diff.new_pages(),
+                diff.existing_pages(),
+                diff.new_visits(),
+                diff.duplicate_visits()
+            );
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(diff)?),
+        OutputFormat::None => {}
     }
+    Ok(())
+}
 
-    // host_str is ASCII so we don't need to watch out for unicode stuff
-    let mut rev_host: String = url
-        .host_str()
-        .expect("URL must have a host.")
-        .chars()
-        .rev()
-        .collect();
-    rev_host.push('.');
-
-    let guid: String = generate_guid();
-
-    let url_hash: u64 = hash::hash(url.as_ref())?;
-
-    let origin_id = find_or_insert_origin(url, transaction)?;
-    let id: u32 = {
-        // create new place entry
-        let mut statement = transaction.prepare_cached(
-            r#"
-            INSERT INTO moz_places
-                (url, title, rev_host, 
-                    last_visit_date, guid,
-                    url_hash, origin_id,
-                    recalc_frecency, 
-                    alt_frecency, recalc_alt_frecency
-                )
-            VALUES (?1, ?2, ?3, NULL, ?4, ?5, ?6, 1, 0, 1)
-            RETURNING id
-            "#,
-        )?;
-        statement.query_row(
-            (&url, &title, &rev_host, &guid, &url_hash, origin_id),
-            |row| row.get(0),
-        )?
-    };
+/// Window of the near-duplicate strategy of `--bench-dedup` without `--dedup-window`, in milliseconds.
+const BENCH_DEDUP_WINDOW: u64 = 1000;
 
-    Ok(id)
+/// Outcome of one strategy of `--bench-dedup`.
+#[derive(serde::Serialize)]
+struct DedupBench {
+    strategy: String,
+    new_visits: u32,
+    duplicate_visits: u32,
+    new_pages: usize,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    duration: Duration,
 }
 
-fn find_or_insert_origin(url: &Url, transaction: &mut Transaction) -> anyhow::Result<u32> {
-    let (prefix, host) = match url.origin() {
-        url::Origin::Opaque(_) => anyhow::bail!("Opaque URLs are not supported."),
-        url::Origin::Tuple(scheme, host, port) => match scheme.as_str() {
-            "https" if port == 443 => (Cow::Borrowed("https://"), host.to_string()),
-            "https" => (Cow::Borrowed("https://"), format!("{}:{}", host, port)),
-            "http" if port == 80 => (Cow::Borrowed("http://"), host.to_string()),
-            "http" => (Cow::Borrowed("http://"), format!("{}:{}", host, port)),
-            _ => (
-                Cow::Owned(format!("{}://", scheme)),
-                format!("{}:{}", host, port),
-            ),
-        },
-    };
-    let id: Option<u32> = transaction
-        .query_row(
-            "SELECT id FROM moz_origins WHERE host = (?1) AND prefix = (?2)",
-            (&host, &prefix),
-            |row| row.get(0),
-        )
-        .optional()?;
-
-    if let Some(id) = id {
-        return Ok(id);
-    }
-
-    let mut statement = transaction.prepare_cached(
-        r#"
-            INSERT INTO moz_origins 
-                (prefix, host, frecency, recalc_frecency, alt_frecency, recalc_alt_frecency) 
-                VALUES (?1, ?2, 0, 1, NULL, 1)
-            RETURNING id
-        "#,
-    )?;
-    let id: u32 = statement.query_row((&prefix, &host), |row| row.get(0))?;
-    Ok(id)
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Works out what importing `entries` would do with each way of telling duplicates apart, like `--dry-run`.
+fn bench_dedup(
+    connection: &rusqlite::Connection,
+    entries: &[Visit],
+    window: u64,
+    touch_existing_places: bool,
+    schema: &SchemaMap,
+) -> anyhow::Result<Vec<DedupBench>> {
+    let mut results = Vec::new();
+    for (strategy, delta, collapse) in [
+        ("timestamp".to_string(), false, false),
+        ("delta".to_string(), true, false),
+        (format!("delta, {window} ms window"), true, true),
+    ] {
+        let start = Instant::now();
+        let (diff, collapsed) = if collapse {
+            let mut entries = entries.to_vec();
+            let collapsed =
+                rewrite::collapse_near_duplicates(&mut entries, window.saturating_mul(1000));
+            let diff = diff::diff(connection, &entries, delta, touch_existing_places, schema)?;
+            (diff, collapsed)
+        } else {
+            let diff = diff::diff(connection, entries, delta, touch_existing_places, schema)?;
+            (diff, 0)
+        };
+        results.push(DedupBench {
+            strategy,
+            new_visits: diff.new_visits(),
+            duplicate_visits: diff.duplicate_visits() + collapsed as u32,
+            new_pages: diff.new_pages(),
+            duration: start.elapsed(),
+        });
+    }
+    Ok(results)
 }
 
-// See: https://searchfox.org/mozilla-central/rev/d0ec1bcdc975afb0f334503c11ea0618125fb750/toolkit/components/places/Helpers.cpp#21
-const GUID_LENGTH: usize = 12;
+fn print_dedup_bench(results: &[DedupBench], format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            println!("STRATEGY\tNEW_VISITS\tDUPLICATES\tNEW_PAGES\tTIME");
+            for result in results {
+                println!(
+                    "{}\t{}\t{}\t{}\t{:.2?}",
+                    result.strategy,
+                    result.new_visits,
+                    result.duplicate_visits,
+                    result.new_pages,
+                    result.duration
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(results)?),
+        OutputFormat::None => {}
+    }
+    Ok(())
+}
 
-const REQUIRED_BYTES_LEN: usize = GUID_LENGTH / 4 * 3;
+/// Parses a number greater than 0 and at most 1, for `--sample`.
+fn parse_fraction(fraction: &str) -> Result<f64, String> {
+    match fraction.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err(format!(
+            "{fraction:?} isn't a fraction greater than 0 and at most 1."
+        )),
+    }
+}
 
-// See: https://searchfox.org/mozilla-central/rev/d0ec1bcdc975afb0f334503c11ea0618125fb750/toolkit/components/places/Helpers.cpp#192
-fn generate_guid() -> String {
-    let mut buffer = [0; REQUIRED_BYTES_LEN];
-    rand::thread_rng().fill_bytes(&mut buffer);
-    base64::engine::general_purpose::URL_SAFE.encode(buffer)
+/// Asks the user a yes or no question on the terminal, defaulting to no.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Can't ask for confirmation without a terminal, pass --yes instead.");
+    }
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
 }
@@ -0,0 +1,174 @@
+//! A log of every import into a database, kept next to it, for the `history` subcommand.
+//!
+//! The manifest of `places.sqlite` is `places.sqlite.imports.jsonl`, with one JSON object per import.
+//! Firefox ignores the file, and it is only ever appended to.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::places::Counts;
+
+/// One import, as recorded in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ImportRecord {
+    /// When the import finished, in microseconds since the unix epoch.
+    pub time: u64,
+    /// The input file, as it was passed.
+    pub source: String,
+    /// [`hash_file`] of the input, unless it was read from stdin.
+    pub source_hash: Option<String>,
+    /// Entries left to import after filtering.
+    pub entries: usize,
+    pub inserted: u64,
+    pub skipped: u64,
+    pub counted: u64,
+    pub opaque: u64,
+    pub failed: u64,
+    /// Label of `--tag`, if the import was tagged.
+    pub tag: Option<String>,
+}
+
+impl ImportRecord {
+    pub fn new(
+        time: u64,
+        source: &Path,
+        entries: usize,
+        counts: Counts,
+        failed: u64,
+        tag: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let source_hash = if source.as_os_str() == "-" {
+            None
+        } else {
+            Some(
+                hash_file(source)
+                    .with_context(|| format!("Failed to read {}.", source.display()))?,
+            )
+        };
+        Ok(Self {
+            time,
+            source: source.display().to_string(),
+            source_hash,
+            entries,
+            inserted: counts.inserted,
+            skipped: counts.skipped,
+            counted: counts.counted,
+            opaque: counts.opaque,
+            failed,
+            tag,
+        })
+    }
+}
+
+/// Path of the manifest of the database at `database`.
+pub fn path(database: &Path) -> PathBuf {
+    let mut path = database.as_os_str().to_owned();
+    path.push(".imports.jsonl");
+    PathBuf::from(path)
+}
+
+/// Appends `record` to the manifest at `path`, creating it if needed.
+pub fn append(path: &Path, record: &ImportRecord) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to write {}.", path.display()))
+}
+
+/// Reads all records of the manifest at `path`, oldest first, or none if there is no manifest.
+pub fn read(path: &Path) -> anyhow::Result<Vec<ImportRecord>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Failed to read {}.", path.display()))
+        }
+    };
+    let mut records = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}.", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line).with_context(|| {
+            format!("Failed to parse line {} of {}.", index + 1, path.display())
+        })?);
+    }
+    Ok(records)
+}
+
+/// Hashes the file at `path` with 64 bit FNV-1a, as e.g. `fnv1a64:af63dc4c8601ec8c`,
+/// to tell whether two imports read the same file. Not meant to withstand deliberate collisions.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hash = OFFSET_BASIS;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for byte in &buffer[..read] {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(PRIME);
+        }
+    }
+    Ok(format!("fnv1a64:{hash:016x}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{append, hash_file, path, read, ImportRecord};
+    use crate::places::Counts;
+
+    #[test]
+    fn test_manifest() {
+        let database = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-manifest-{}.sqlite",
+            std::process::id()
+        ));
+        let manifest = path(&database);
+        assert_eq!(
+            manifest.file_name().unwrap(),
+            database
+                .with_extension("sqlite.imports.jsonl")
+                .file_name()
+                .unwrap()
+        );
+        let _ = std::fs::remove_file(&manifest);
+        assert_eq!(read(&manifest).unwrap(), []);
+
+        // the standard test vector of FNV-1a
+        std::fs::write(&database, "a").unwrap();
+        assert_eq!(hash_file(&database).unwrap(), "fnv1a64:af63dc4c8601ec8c");
+
+        let counts = Counts {
+            inserted: 3,
+            skipped: 1,
+            ..Counts::default()
+        };
+        let first = ImportRecord::new(1000, &database, 4, counts, 0, None).unwrap();
+        let second =
+            ImportRecord::new(2000, Path::new("-"), 4, counts, 1, Some("takeout".into())).unwrap();
+        assert_eq!(second.source_hash, None);
+        append(&manifest, &first).unwrap();
+        append(&manifest, &second).unwrap();
+        assert_eq!(read(&manifest).unwrap(), [first, second]);
+
+        std::fs::remove_file(&manifest).unwrap();
+        std::fs::remove_file(&database).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{regex::Regex, visit::Visit};
+
+/// Schemes of browser internal pages which aren't worth keeping, even though Firefox could store them.
+///
+/// Schemes Firefox can't store at all are reported by [`crate::health`] instead.
+pub const DEFAULT_SCHEMES: &[&str] = &[
+    "chrome-extension",
+    "chrome-native",
+    "chrome-search",
+    "chrome-untrusted",
+    "devtools",
+    "filesystem",
+];
+
+/// Titles of pages which were left before they finished loading, or never had content.
+pub const DEFAULT_TITLES: &[&str] = &[
+    r"^(New Tab|Untitled|about:blank)$",
+    r"^(Loading|Redirecting)(\.\.\.|…)?$",
+];
+
+/// Visits shorter than this many milliseconds are usually redirects or accidental clicks.
+pub const DEFAULT_MIN_DURATION: u64 = 1000;
+
+/// Rules deciding which visits are noise, used by `--exclude-noise`.
+///
+/// A visit is noise if any of the rules matches it.
+#[derive(Debug, Clone)]
+pub struct NoiseRules {
+    /// URL schemes whose visits are noise.
+    pub schemes: Vec<String>,
+    /// Patterns matched against titles. Visits without a title never match.
+    pub titles: Vec<Regex>,
+    /// Visits which lasted fewer milliseconds than this are noise.
+    /// Visits without a known duration never match, `0` disables the rule.
+    pub min_duration: u64,
+}
+
+impl Default for NoiseRules {
+    fn default() -> Self {
+        Self {
+            schemes: DEFAULT_SCHEMES
+                .iter()
+                .map(|scheme| scheme.to_string())
+                .collect(),
+            titles: DEFAULT_TITLES
+                .iter()
+                .map(|pattern| pattern.parse().expect("default patterns are valid"))
+                .collect(),
+            min_duration: DEFAULT_MIN_DURATION,
+        }
+    }
+}
+
+impl NoiseRules {
+    pub fn is_noise(&self, visit: &Visit) -> bool {
+        self.schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(visit.url.scheme()))
+            || visit
+                .title
+                .as_deref()
+                .is_some_and(|title| self.titles.iter().any(|regex| regex.is_match(title)))
+            || visit
+                .duration
+                .is_some_and(|duration| duration < self.min_duration * 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::NoiseRules;
+    use crate::visit::Visit;
+
+    fn visit(url: &str, title: Option<&str>, duration: Option<u64>) -> Visit {
+        let url: Url = url.parse().unwrap();
+        Visit {
+            title: title.map(str::to_string),
+            duration,
+            ..Visit::new(url, 1000)
+        }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let rules = NoiseRules::default();
+        let cases = [
+            (
+                visit("https://www.mozilla.org/", Some("Mozilla"), None),
+                false,
+            ),
+            (visit("chrome-extension://abc/popup.html", None, None), true),
+            (
+                visit("https://www.mozilla.org/", Some("New Tab"), None),
+                true,
+            ),
+            (
+                visit("https://www.mozilla.org/", Some("Loading…"), None),
+                true,
+            ),
+            (
+                visit("https://www.mozilla.org/", Some("Loading times"), None),
+                false,
+            ),
+            (visit("https://www.mozilla.org/", None, Some(300_000)), true),
+            (
+                visit("https://www.mozilla.org/", None, Some(5_000_000)),
+                false,
+            ),
+        ];
+        for (visit, noise) in cases {
+            assert_eq!(rules.is_noise(&visit), noise, "{visit:?}");
+        }
+    }
+
+    #[test]
+    fn test_adjusted_rules() {
+        let rules = NoiseRules {
+            schemes: vec!["file".to_string()],
+            titles: Vec::new(),
+            min_duration: 0,
+        };
+        assert!(rules.is_noise(&visit("file:///home/user/a.html", None, None)));
+        assert!(!rules.is_noise(&visit("chrome-extension://abc/", Some("New Tab"), Some(1))));
+    }
+}
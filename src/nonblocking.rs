@@ -0,0 +1,228 @@
+//! An async wrapper around [`FirefoxHistory`], for embedding the import in a GUI
+//! without blocking its UI thread. Needs the `async` feature.
+//!
+//! SQLite only offers blocking calls, so every import runs on a thread of its own, like `spawn_blocking`
+//! of the async runtimes, and the returned future completes when it is done. The future doesn't depend
+//! on a runtime, so it can be awaited on any executor. Progress is sent over a [`std::sync::mpsc`] channel
+//! after every committed batch, which a UI thread can poll with [`std::sync::mpsc::Receiver::try_recv`].
+//!
+//! Dropping the future doesn't cancel the import, it finishes in the background,
+//! and the history can be used again afterwards.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{mpsc::Sender, Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    places::{Counts, FirefoxHistory},
+    visit::Visit,
+};
+
+/// How far [`AsyncFirefoxHistory::insert_visits`] got.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct ImportProgress {
+    /// Visits whose batch was committed.
+    pub processed: usize,
+    /// Visits to import.
+    pub total: usize,
+    /// What happened to the committed visits.
+    pub counts: Counts,
+    /// Visits which failed to convert.
+    pub failed: u64,
+}
+
+/// A [`FirefoxHistory`] whose imports run on another thread.
+pub struct AsyncFirefoxHistory {
+    /// The history while no import runs, an import takes it and puts it back when it is done.
+    history: Arc<Mutex<Option<FirefoxHistory>>>,
+}
+
+impl AsyncFirefoxHistory {
+    pub fn new(history: FirefoxHistory) -> Self {
+        Self {
+            history: Arc::new(Mutex::new(Some(history))),
+        }
+    }
+
+    /// Imports `visits` in batches of `batch_size`, each in a transaction, like the command line does.
+    /// Visits which fail to convert are counted and skipped. After every batch, the progress so far is
+    /// sent to `progress`, if it is given and its receiver still exists.
+    ///
+    /// Fails right away if another import of this history still runs.
+    pub fn insert_visits(
+        &self,
+        visits: Vec<Visit>,
+        batch_size: usize,
+        progress: Option<Sender<ImportProgress>>,
+    ) -> impl Future<Output = anyhow::Result<ImportProgress>> {
+        let slot = Arc::clone(&self.history);
+        let history = self.take();
+        async move {
+            let mut history = history?;
+            spawn_blocking(move || {
+                let result = import(&mut history, &visits, batch_size.max(1), progress);
+                *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(history);
+                result
+            })
+            .await
+        }
+    }
+
+    /// Returns the wrapped history, unless an import still runs.
+    pub fn into_inner(self) -> anyhow::Result<FirefoxHistory> {
+        self.take()
+    }
+
+    fn take(&self) -> anyhow::Result<FirefoxHistory> {
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Another import still runs, or panicked and took the history with it."
+                )
+            })
+    }
+}
+
+fn import(
+    history: &mut FirefoxHistory,
+    visits: &[Visit],
+    batch_size: usize,
+    progress: Option<Sender<ImportProgress>>,
+) -> anyhow::Result<ImportProgress> {
+    let start = history.counts();
+    let mut state = ImportProgress {
+        total: visits.len(),
+        ..ImportProgress::default()
+    };
+    for chunk in visits.chunks(batch_size) {
+        let mut batch = history.begin()?;
+        for visit in chunk {
+            if batch.insert_visit(visit).is_err() {
+                state.failed += 1;
+            }
+        }
+        batch.commit()?;
+        state.processed += chunk.len();
+        state.counts = history.counts().since(start);
+        if let Some(progress) = &progress {
+            // a receiver which is gone isn't interested anymore, the import goes on anyway
+            let _ = progress.send(state);
+        }
+    }
+    Ok(state)
+}
+
+struct Shared<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Completes with the result of a closure running on another thread.
+struct BlockingTask<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn spawn_blocking<T: Send + 'static>(
+    function: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> BlockingTask<anyhow::Result<T>> {
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let task = BlockingTask {
+        shared: Arc::clone(&shared),
+    };
+    std::thread::spawn(move || {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(function))
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("The import panicked.")));
+        let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    task
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{mpsc, Arc},
+        task::{Context, Poll, Wake},
+        thread::Thread,
+    };
+
+    use url::Url;
+
+    use super::AsyncFirefoxHistory;
+    use crate::{places::FirefoxHistoryBuilder, visit::Visit};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Runs `future` to completion on the current thread, like the executor of a GUI would.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut context = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_visits() {
+        let history = FirefoxHistoryBuilder::new()
+            .create_schema(true)
+            .open_in_memory()
+            .unwrap();
+        let history = AsyncFirefoxHistory::new(history);
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let visits: Vec<Visit> = (1..=5)
+            .map(|time| Visit::new(url.clone(), time * 1000))
+            .collect();
+
+        let (sender, receiver) = mpsc::channel();
+        let result = block_on(history.insert_visits(visits.clone(), 2, Some(sender))).unwrap();
+        assert_eq!((result.processed, result.total), (5, 5));
+        assert_eq!(result.counts.inserted, 5);
+        let processed: Vec<usize> = receiver.iter().map(|progress| progress.processed).collect();
+        assert_eq!(processed, [2, 4, 5]);
+
+        // the history is back, and knows the visits now
+        let result = block_on(history.insert_visits(visits, 10, None)).unwrap();
+        assert_eq!((result.counts.inserted, result.counts.skipped), (0, 5));
+        assert_eq!(history.into_inner().unwrap().count_visits().unwrap(), 5);
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A Firefox profile listed in `profiles.ini`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_default: bool,
+}
+
+/// Whether the `places.sqlite` of a profile can be imported into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileStatus {
+    Writable,
+    /// Firefox is probably running with this profile.
+    Locked,
+    ReadOnly,
+    Missing,
+}
+
+impl fmt::Display for ProfileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Writable => "writable",
+            Self::Locked => "locked",
+            Self::ReadOnly => "read-only",
+            Self::Missing => "missing",
+        })
+    }
+}
+
+impl Profile {
+    pub fn places_path(&self) -> PathBuf {
+        self.path.join("places.sqlite")
+    }
+
+    pub fn status(&self) -> ProfileStatus {
+        let Ok(metadata) = self.places_path().metadata() else {
+            return ProfileStatus::Missing;
+        };
+        // Firefox keeps `lock` (a dangling symlink on unix) around while it's running
+        if self.path.join("lock").symlink_metadata().is_ok() {
+            return ProfileStatus::Locked;
+        }
+        if metadata.permissions().readonly() {
+            return ProfileStatus::ReadOnly;
+        }
+        ProfileStatus::Writable
+    }
+}
+
+/// Applications sharing Firefox's profile and places format.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TargetApp {
+    #[default]
+    Firefox,
+    /// Only recent versions have a places schema this tool can write to.
+    Thunderbird,
+    /// Its places schema is too old to import into, but its profiles can be listed.
+    Seamonkey,
+}
+
+impl TargetApp {
+    /// Directories the application keeps its `profiles.ini` in on this platform, in the order they are checked.
+    pub fn directories(self) -> Vec<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let mut directories = Vec::new();
+        if cfg!(target_os = "windows") {
+            if let Some(app_data) = std::env::var_os("APPDATA") {
+                let app_data = PathBuf::from(app_data);
+                directories.push(match self {
+                    Self::Firefox => app_data.join("Mozilla").join("Firefox"),
+                    Self::Thunderbird => app_data.join("Thunderbird"),
+                    Self::Seamonkey => app_data.join("Mozilla").join("SeaMonkey"),
+                });
+            }
+        } else if cfg!(target_os = "macos") {
+            if let Some(home) = &home {
+                directories.push(match self {
+                    Self::Firefox => home.join("Library/Application Support/Firefox"),
+                    Self::Thunderbird => home.join("Library/Thunderbird"),
+                    Self::Seamonkey => home.join("Library/Application Support/SeaMonkey"),
+                });
+            }
+        } else {
+            let config = std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home.as_ref().map(|home| home.join(".config")));
+            match self {
+                Self::Firefox => {
+                    if let Some(home) = &home {
+                        directories.push(home.join(".mozilla/firefox"));
+                    }
+                    if let Some(config) = config {
+                        directories.push(config.join("mozilla/firefox"));
+                    }
+                    if let Some(home) = &home {
+                        directories.push(home.join("snap/firefox/common/.mozilla/firefox"));
+                        directories
+                            .push(home.join(".var/app/org.mozilla.firefox/.mozilla/firefox"));
+                    }
+                }
+                Self::Thunderbird => {
+                    if let Some(home) = &home {
+                        directories.push(home.join(".thunderbird"));
+                        directories.push(home.join("snap/thunderbird/common/.thunderbird"));
+                        directories
+                            .push(home.join(".var/app/org.mozilla.Thunderbird/.thunderbird"));
+                    }
+                }
+                Self::Seamonkey => {
+                    if let Some(home) = &home {
+                        directories.push(home.join(".mozilla/seamonkey"));
+                    }
+                }
+            }
+        }
+        directories
+    }
+}
+
+/// Finds all profiles in the `profiles.ini` files of the [`TargetApp::directories`].
+pub fn discover(app: TargetApp) -> anyhow::Result<Vec<Profile>> {
+    let mut profiles = Vec::new();
+    for directory in app.directories() {
+        let ini = directory.join("profiles.ini");
+        if !ini.exists() {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&ini)?;
+        profiles.extend(parse_profiles_ini(&contents, &directory));
+    }
+    Ok(profiles)
+}
+
+/// Parses the `[ProfileN]` sections of a `profiles.ini` in `directory`.
+pub fn parse_profiles_ini(contents: &str, directory: &Path) -> Vec<Profile> {
+    struct Section {
+        name: Option<String>,
+        path: Option<String>,
+        is_relative: bool,
+        is_default: bool,
+    }
+
+    let mut sections = Vec::new();
+    let mut current: Option<Section> = None;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') && line.ends_with(']') {
+            sections.extend(current.take());
+            if line[1..line.len() - 1].starts_with("Profile") {
+                current = Some(Section {
+                    name: None,
+                    path: None,
+                    is_relative: true,
+                    is_default: false,
+                });
+            }
+            continue;
+        }
+        let (Some(section), Some((key, value))) = (&mut current, line.split_once('=')) else {
+            continue;
+        };
+        match key.trim() {
+            "Name" => section.name = Some(value.trim().to_string()),
+            "Path" => section.path = Some(value.trim().to_string()),
+            "IsRelative" => section.is_relative = value.trim() == "1",
+            "Default" => section.is_default = value.trim() == "1",
+            _ => {}
+        }
+    }
+    sections.extend(current);
+
+    sections
+        .into_iter()
+        .filter_map(|section| {
+            let path = section.path?;
+            Some(Profile {
+                name: section.name.unwrap_or_else(|| path.clone()),
+                path: if section.is_relative {
+                    directory.join(path)
+                } else {
+                    PathBuf::from(path)
+                },
+                is_default: section.is_default,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{parse_profiles_ini, Profile};
+
+    #[test]
+    fn test_parse_profiles_ini() {
+        let contents = r#"
+[Install4F96D1932A9F858E]
+Default=abcdefgh.default-release
+Locked=1
+
+[Profile1]
+Name=default
+IsRelative=1
+Path=ijklmnop.default
+
+[Profile0]
+Name=default-release
+IsRelative=1
+Path=abcdefgh.default-release
+Default=1
+
+[Profile2]
+Name=elsewhere
+IsRelative=0
+Path=/mnt/profiles/elsewhere
+
+[General]
+StartWithLastProfile=1
+Version=2
+"#;
+        let directory = Path::new("/home/user/.mozilla/firefox");
+        assert_eq!(
+            parse_profiles_ini(contents, directory),
+            [
+                Profile {
+                    name: "default".to_string(),
+                    path: directory.join("ijklmnop.default"),
+                    is_default: false,
+                },
+                Profile {
+                    name: "default-release".to_string(),
+                    path: directory.join("abcdefgh.default-release"),
+                    is_default: true,
+                },
+                Profile {
+                    name: "elsewhere".to_string(),
+                    path: PathBuf::from("/mnt/profiles/elsewhere"),
+                    is_default: false,
+                },
+            ]
+        );
+    }
+}
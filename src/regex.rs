@@ -0,0 +1,710 @@
+//! A small regular expression engine.
+//!
+//! Supports the commonly used subset of the usual syntax:
+//! literals, `.`, character classes like `[a-z]` and `[^/]`, the escapes `\d`, `\w`, `\s` and their negations,
+//! the anchors `^` and `$`, capturing `(...)` and non-capturing `(?:...)` groups, alternation with `|`
+//! and the greedy or lazy quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`.
+//!
+//! Patterns are compiled to a program which is run by following all of its paths through the input at once
+//! (a Pike VM), so matching takes time linear in the length of the input and never recurses per character,
+//! even for patterns like `(a+)+b`. Matches are the ones a backtracking engine would find first.
+
+use std::{fmt, iter::Peekable, str::Chars, str::FromStr};
+
+/// Patterns whose program would be longer are rejected, e.g. because of huge counted repetitions.
+const MAX_PROGRAM_LENGTH: usize = 100_000;
+
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    program: Vec<Instruction>,
+    groups: usize,
+    /// Capture slots followed by the slots of [`Instruction::Progress`].
+    slots: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| start <= c && c <= end)
+            != self.negated
+    }
+}
+
+/// An instruction of a compiled pattern. Jumps are indices into the program.
+#[derive(Clone, Debug)]
+enum Instruction {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Records the current position in a capture slot, two per group.
+    Save(usize),
+    /// Fails if the position is still the one recorded in a slot, so a repetition doesn't repeat
+    /// an empty match once it has its minimum, like backtracking engines do.
+    Progress(usize),
+    /// Continues at both, the first one is preferred.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(source: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            chars: source.chars().peekable(),
+            groups: 0,
+        };
+        let node = parser.parse_alternation()?;
+        if let Some(c) = parser.chars.next() {
+            anyhow::bail!("Invalid pattern {source:?}: unexpected {c:?}.");
+        }
+        let mut program = vec![Instruction::Save(0)];
+        let mut slots = (parser.groups + 1) * 2;
+        compile(&node, &mut program, &mut slots);
+        program.push(Instruction::Save(1));
+        program.push(Instruction::Match);
+        if program.len() > MAX_PROGRAM_LENGTH {
+            anyhow::bail!("Pattern {source:?} is too large.");
+        }
+        Ok(Self {
+            source: source.to_string(),
+            program,
+            groups: parser.groups,
+            slots,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.find_at(&chars, 0).is_some()
+    }
+
+    /// Replaces all non-overlapping matches in `text`.
+    ///
+    /// `$0` to `$9` in the replacement insert the corresponding capture group, `$$` inserts a literal `$`.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::with_capacity(text.len());
+        let mut position = 0;
+        while position <= chars.len() {
+            let Some(captures) = self.find_at(&chars, position) else {
+                break;
+            };
+            let (start, end) = captures[0].expect("whole match is always captured");
+            result.extend(&chars[position..start]);
+            expand(replacement, &chars, &captures, &mut result);
+            if end == start {
+                // don't match the same empty string again
+                result.extend(chars.get(end));
+                position = end + 1;
+            } else {
+                position = end;
+            }
+        }
+        if position < chars.len() {
+            result.extend(&chars[position..]);
+        }
+        result
+    }
+
+    /// Finds the first match starting at or after `from`.
+    ///
+    /// Threads are kept in the order a backtracking engine would try them, so once a thread matches,
+    /// the ones after it are dropped, and the ones before it may still find a preferred match.
+    fn find_at(&self, chars: &[char], from: usize) -> Option<Captures> {
+        let slots = vec![None; self.slots];
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        for position in from..=chars.len() {
+            if matched.is_none() {
+                // a match starting here is only preferred over none at all
+                self.add_thread(&mut current, 0, position, chars.len(), slots.clone());
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+            for (pc, slots) in current.list.drain(..) {
+                match &self.program[pc] {
+                    Instruction::Match => {
+                        matched = Some(slots);
+                        break;
+                    }
+                    instruction => {
+                        let advances = chars.get(position).is_some_and(|&c| match instruction {
+                            Instruction::Char(expected) => c == *expected,
+                            Instruction::Any => true,
+                            Instruction::Class(class) => class.matches(c),
+                            _ => false,
+                        });
+                        if advances {
+                            self.add_thread(&mut next, pc + 1, position + 1, chars.len(), slots);
+                        }
+                    }
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        matched.map(|slots| {
+            slots[..(self.groups + 1) * 2]
+                .chunks(2)
+                .map(|pair| pair[0].zip(pair[1]))
+                .collect()
+        })
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps, splits, saves and anchors right away,
+    /// so `threads` only holds threads waiting for the next character or at a match.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        position: usize,
+        length: usize,
+        slots: Vec<Option<usize>>,
+    ) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            // checked first, so a thread which fails here doesn't keep others from passing
+            if let Instruction::Progress(slot) = self.program[pc] {
+                if slots[slot] == Some(position) {
+                    continue;
+                }
+            }
+            // an earlier thread at the same instruction is preferred, and would do the same from here on
+            if !threads.visit(pc) {
+                continue;
+            }
+            match &self.program[pc] {
+                Instruction::Jump(target) => stack.push((*target, slots)),
+                Instruction::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Instruction::Save(slot) => {
+                    slots[*slot] = Some(position);
+                    stack.push((pc + 1, slots));
+                }
+                Instruction::Progress(_) => stack.push((pc + 1, slots)),
+                Instruction::Start => {
+                    if position == 0 {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Instruction::End => {
+                    if position == length {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Instruction::Char(_)
+                | Instruction::Any
+                | Instruction::Class(_)
+                | Instruction::Match => threads.list.push((pc, slots)),
+            }
+        }
+    }
+}
+
+impl FromStr for Regex {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> anyhow::Result<Self> {
+        Self::new(source)
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.source).finish()
+    }
+}
+
+/// Threads of one step, in order of preference, with the instructions already visited in this step.
+struct Threads {
+    list: Vec<(usize, Vec<Option<usize>>)>,
+    visited: Vec<bool>,
+}
+
+impl Threads {
+    fn new(length: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            visited: vec![false; length],
+        }
+    }
+
+    /// Marks `pc` as visited, returns false if it already was.
+    fn visit(&mut self, pc: usize) -> bool {
+        !std::mem::replace(&mut self.visited[pc], true)
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.visited.fill(false);
+    }
+}
+
+/// Appends the instructions of `node` to `program`, `slots` is the number of slots used so far.
+fn compile(node: &Node, program: &mut Vec<Instruction>, slots: &mut usize) {
+    // keeps huge counted repetitions from allocating without bounds, the caller rejects the result
+    if program.len() > MAX_PROGRAM_LENGTH {
+        return;
+    }
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Instruction::Char(*c)),
+        Node::Any => program.push(Instruction::Any),
+        Node::Class(class) => program.push(Instruction::Class(class.clone())),
+        Node::Start => program.push(Instruction::Start),
+        Node::End => program.push(Instruction::End),
+        Node::Group(inner, None) => compile(inner, program, slots),
+        Node::Group(inner, Some(index)) => {
+            program.push(Instruction::Save(index * 2));
+            compile(inner, program, slots);
+            program.push(Instruction::Save(index * 2 + 1));
+        }
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program, slots);
+            }
+        }
+        Node::Alternation(alternatives) => {
+            let mut jumps = Vec::new();
+            for (index, alternative) in alternatives.iter().enumerate() {
+                if index + 1 == alternatives.len() {
+                    compile(alternative, program, slots);
+                } else {
+                    let split = program.len();
+                    program.push(Instruction::Split(split + 1, 0));
+                    compile(alternative, program, slots);
+                    jumps.push(program.len());
+                    program.push(Instruction::Jump(0));
+                    let next = program.len();
+                    program[split] = Instruction::Split(split + 1, next);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Instruction::Jump(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                if program.len() > MAX_PROGRAM_LENGTH {
+                    return;
+                }
+                compile(node, program, slots);
+            }
+            // prefers another iteration if greedy, leaving the repetition otherwise
+            let split = |body, out| {
+                if *greedy {
+                    Instruction::Split(body, out)
+                } else {
+                    Instruction::Split(out, body)
+                }
+            };
+            let slot = *slots;
+            *slots += 1;
+            // an optional iteration, which must not match the empty string
+            let iteration = |program: &mut Vec<Instruction>, slots: &mut usize| {
+                let start = program.len();
+                program.push(Instruction::Jump(0));
+                program.push(Instruction::Save(slot));
+                compile(node, program, slots);
+                program.push(Instruction::Progress(slot));
+                start
+            };
+            match max {
+                None => {
+                    let start = iteration(program, slots);
+                    program.push(Instruction::Jump(start));
+                    let end = program.len();
+                    program[start] = split(start + 1, end);
+                }
+                Some(max) => {
+                    let mut starts = Vec::new();
+                    for _ in *min..*max {
+                        if program.len() > MAX_PROGRAM_LENGTH {
+                            return;
+                        }
+                        starts.push(iteration(program, slots));
+                    }
+                    let end = program.len();
+                    for start in starts {
+                        program[start] = split(start + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn expand(replacement: &str, chars: &[char], captures: &Captures, result: &mut String) {
+    let mut replacement = replacement.chars().peekable();
+    while let Some(c) = replacement.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        match replacement.peek().copied() {
+            Some('$') => {
+                replacement.next();
+                result.push('$');
+            }
+            Some(digit @ '0'..='9') => {
+                replacement.next();
+                let index = digit as usize - '0' as usize;
+                if let Some(Some((start, end))) = captures.get(index) {
+                    result.extend(&chars[*start..*end]);
+                }
+            }
+            _ => result.push('$'),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    groups: usize,
+}
+
+impl Parser<'_> {
+    fn parse_alternation(&mut self) -> anyhow::Result<Node> {
+        let mut alternatives = vec![self.parse_concat()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            alternatives.push(self.parse_concat()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().expect("one alternative")
+        } else {
+            Node::Alternation(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self) -> anyhow::Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().expect("one node"),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self) -> anyhow::Result<Node> {
+        let c = self.chars.next().expect("caller checked for more input");
+        Ok(match c {
+            '(' => {
+                let index = if self.chars.next_if_eq(&'?').is_some() {
+                    if self.chars.next_if_eq(&':').is_none() {
+                        anyhow::bail!("Only non-capturing groups (?:...) are supported.");
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.parse_alternation()?;
+                if self.chars.next_if_eq(&')').is_none() {
+                    anyhow::bail!("Unclosed group in pattern.");
+                }
+                Node::Group(Box::new(inner), index)
+            }
+            '[' => Node::Class(self.parse_class()?),
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => self.parse_escape()?,
+            '*' | '+' | '?' => anyhow::bail!("Quantifier {c:?} has nothing to repeat."),
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_escape(&mut self) -> anyhow::Result<Node> {
+        let Some(c) = self.chars.next() else {
+            anyhow::bail!("Pattern ends with an escape.");
+        };
+        if let Some(class) = escape_class(c) {
+            return Ok(Node::Class(class));
+        }
+        Ok(Node::Char(escape_char(c)?))
+    }
+
+    fn parse_class(&mut self) -> anyhow::Result<Class> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.chars.next() else {
+                anyhow::bail!("Unclosed character class in pattern.");
+            };
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let start = if c == '\\' {
+                let Some(escaped) = self.chars.next() else {
+                    anyhow::bail!("Pattern ends with an escape.");
+                };
+                if let Some(class) = escape_class(escaped) {
+                    if class.negated {
+                        anyhow::bail!("Negated escapes are not supported in character classes.");
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                escape_char(escaped)?
+            } else {
+                c
+            };
+
+            let mut lookahead = self.chars.clone();
+            if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|&c| c != ']') {
+                self.chars.next();
+                let mut end = self.chars.next().expect("checked by lookahead");
+                if end == '\\' {
+                    let Some(escaped) = self.chars.next() else {
+                        anyhow::bail!("Pattern ends with an escape.");
+                    };
+                    end = escape_char(escaped)?;
+                }
+                if end < start {
+                    anyhow::bail!("Invalid range {start:?}-{end:?} in character class.");
+                }
+                ranges.push((start, end));
+            } else {
+                ranges.push((start, start));
+            }
+        }
+        Ok(Class { ranges, negated })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> anyhow::Result<Node> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => self.single(0, None),
+            Some('+') => self.single(1, None),
+            Some('?') => self.single(0, Some(1)),
+            Some('{') => match self.parse_counted() {
+                Some(bounds) => bounds,
+                // not a valid repetition, so it's a literal brace
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        if let Some(max) = max {
+            if max < min {
+                anyhow::bail!("Invalid repetition {{{min},{max}}}.");
+            }
+        }
+        if matches!(atom, Node::Start | Node::End) {
+            anyhow::bail!("Anchors can't be repeated.");
+        }
+        let greedy = self.chars.next_if_eq(&'?').is_none();
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    fn single(&mut self, min: usize, max: Option<usize>) -> (usize, Option<usize>) {
+        self.chars.next();
+        (min, max)
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, only consuming the input if it is valid.
+    fn parse_counted(&mut self) -> Option<(usize, Option<usize>)> {
+        let mut lookahead = self.chars.clone();
+        lookahead.next();
+        let mut body = String::new();
+        for c in lookahead.by_ref() {
+            if c == '}' {
+                break;
+            }
+            body.push(c);
+        }
+        let bounds = match body.split_once(',') {
+            None => {
+                let count = body.parse().ok()?;
+                (count, Some(count))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        self.chars = lookahead;
+        Some(bounds)
+    }
+}
+
+fn escape_class(c: char) -> Option<Class> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => return None,
+    };
+    Some(Class {
+        ranges,
+        negated: c.is_ascii_uppercase(),
+    })
+}
+
+fn escape_char(c: char) -> anyhow::Result<char> {
+    Ok(match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c if c.is_ascii_alphanumeric() => anyhow::bail!("Unsupported escape \\{c} in pattern."),
+        c => c,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(is_match(r"\?token=", "https://example.com/?token=abc"));
+        assert!(!is_match(r"\?token=", "https://example.com/?a=token="));
+        assert!(is_match(
+            r"[?&]token=",
+            "https://example.com/?a=1&token=abc"
+        ));
+        assert!(is_match(
+            r"^https?://(www\.)?example\.com/",
+            "http://example.com/a"
+        ));
+        assert!(!is_match(
+            r"^https?://(www\.)?example\.com/",
+            "https://example.org/"
+        ));
+        assert!(is_match(r"^a(b|cd)*e$", "abcdbe"));
+        assert!(!is_match(r"^a(b|cd)*e$", "abce"));
+        assert!(is_match(r"^\d{4}-\d{2}$", "2025-01"));
+        assert!(!is_match(r"^\d{4}-\d{2}$", "2025-1"));
+        assert!(is_match(r"^[^/]+$", "example.com"));
+        assert!(!is_match(r"^[^/]+$", "example.com/"));
+        assert!(is_match(r"^a{2,}$", "aaa"));
+        assert!(!is_match(r"^a{2,3}$", "aaaa"));
+        assert!(is_match(r"^(a*)*$", "aaa"));
+        assert!(is_match(r"x{y", "x{y"));
+        assert!(is_match(r"^[\w.-]+$", "foo-bar.baz_1"));
+        assert!(is_match(r"^\S+\s\S+$", "a b"));
+        assert!(is_match(r"$", "abc"));
+        assert!(is_match(r"c$", "abc"));
+    }
+
+    #[test]
+    fn test_long_input() {
+        // a URL as long as Firefox allows, which would overflow the stack if every character recursed
+        let url = format!("https://example.com/{}", "a".repeat(65536 - 20));
+        assert!(is_match(r"^https://example\.com/.*", &url));
+        assert!(is_match(r"^https://example\.com/.*a$", &url));
+        assert!(!is_match(r"^https://example\.com/.*b", &url));
+
+        // nested quantifiers take time linear in the input, instead of trying every way to split it
+        let text = "a".repeat(100_000);
+        assert!(!is_match(r"(a+)+b", &text));
+        assert!(is_match(r"^(a+)+$", &text));
+        assert_eq!(
+            Regex::new(r"(a|aa)+c").unwrap().replace_all(&text, ""),
+            text
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for pattern in [
+            "(",
+            "a)",
+            "[a",
+            "*a",
+            r"\",
+            r"\q",
+            "[z-a]",
+            "a{3,1}",
+            "^*",
+            "a{1000000}",
+        ] {
+            assert!(
+                Regex::new(pattern).is_err(),
+                "{pattern:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let regex = Regex::new(r" - Google (Search|Suche)$").unwrap();
+        assert_eq!(regex.replace_all("rust - Google Search", ""), "rust");
+        assert_eq!(
+            regex.replace_all("rust - Google Maps", ""),
+            "rust - Google Maps"
+        );
+
+        let regex = Regex::new(r"(\w+)@(\w+)").unwrap();
+        assert_eq!(
+            regex.replace_all("a@b, c@d", "$2 at $1 costs $$1"),
+            "b at a costs $1, d at c costs $1"
+        );
+
+        let regex = Regex::new(r"a*").unwrap();
+        assert_eq!(regex.replace_all("baac", "-"), "-b--c-");
+
+        let regex = Regex::new(r"<.+?>").unwrap();
+        assert_eq!(regex.replace_all("<a><b>c", ""), "c");
+    }
+}
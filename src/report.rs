@@ -0,0 +1,212 @@
+//! A standalone HTML recap of an import for `--html-report`.
+//!
+//! The charts are inline SVG and the styles are inline too, so the file opens offline and can be shared as is.
+
+use std::{collections::HashMap, io::Write};
+
+use chrono::NaiveDate;
+
+use crate::{export::escape_html, places::Counts, visit::Visit};
+
+/// How many hosts [`ImportStats::new`] keeps.
+pub const TOP_HOSTS: usize = 10;
+
+/// Visits of an import aggregated for the report.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Visits by day in the time zone of the report, from the first to the last day with visits,
+    /// including the days without visits in between.
+    pub days: Vec<(NaiveDate, u64)>,
+    /// The [`TOP_HOSTS`] hosts with the most visits, most first. URLs without a host count by their scheme.
+    pub top_hosts: Vec<(String, u64)>,
+}
+
+impl ImportStats {
+    pub fn new(visits: &[Visit], tz: chrono_tz::Tz) -> Self {
+        let mut days: HashMap<NaiveDate, u64> = HashMap::new();
+        let mut hosts: HashMap<&str, u64> = HashMap::new();
+        for visit in visits {
+            let seconds = (visit.time / 1_000_000) as i64;
+            if let Some(time) = chrono::DateTime::from_timestamp(seconds, 0) {
+                *days
+                    .entry(time.with_timezone(&tz).date_naive())
+                    .or_default() += 1;
+            }
+            let host = visit.url.host_str().unwrap_or(visit.url.scheme());
+            *hosts.entry(host).or_default() += 1;
+        }
+
+        let mut stats = ImportStats::default();
+        if let (Some(first), Some(last)) = (days.keys().min(), days.keys().max()) {
+            stats.days = first
+                .iter_days()
+                .take_while(|day| day <= last)
+                .map(|day| (day, days.get(&day).copied().unwrap_or(0)))
+                .collect();
+        }
+        let mut hosts: Vec<(String, u64)> = hosts
+            .into_iter()
+            .map(|(host, visits)| (host.to_string(), visits))
+            .collect();
+        hosts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        hosts.truncate(TOP_HOSTS);
+        stats.top_hosts = hosts;
+        stats
+    }
+}
+
+/// Writes the report of an import of `entries` entries with the outcome `counts`.
+pub fn write_html_report(
+    mut writer: impl Write,
+    stats: &ImportStats,
+    entries: usize,
+    counts: Counts,
+    failed: u64,
+) -> std::io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, r#"<html lang="en"><head><meta charset="utf-8">"#)?;
+    writeln!(writer, "<title>History import report</title>")?;
+    writeln!(
+        writer,
+        "<style>body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; }} \
+         svg {{ display: block; width: 100%; }} rect {{ fill: #0060df; }} td {{ padding: 0.2em 1em 0.2em 0; }}</style>"
+    )?;
+    writeln!(writer, "</head><body>")?;
+    writeln!(writer, "<h1>History import report</h1>")?;
+
+    writeln!(writer, "<h2>Import</h2>")?;
+    writeln!(writer, "<table>")?;
+    for (label, count) in [
+        ("Entries", entries as u64),
+        ("Inserted", counts.inserted),
+        ("Already existed", counts.skipped),
+        ("Only counted", counts.counted),
+        ("Opaque URL", counts.opaque),
+        ("Failed", failed),
+    ] {
+        writeln!(writer, "<tr><td>{label}</td><td>{count}</td></tr>")?;
+    }
+    writeln!(writer, "</table>")?;
+
+    writeln!(writer, "<h2>Visits per day</h2>")?;
+    match (stats.days.first(), stats.days.last()) {
+        (Some((first, _)), Some((last, _))) => {
+            let max = stats
+                .days
+                .iter()
+                .map(|(_, visits)| *visits)
+                .max()
+                .unwrap_or(1);
+            writeln!(
+                writer,
+                r#"<svg viewBox="0 0 {} 100" height="200" preserveAspectRatio="none">"#,
+                stats.days.len()
+            )?;
+            for (index, (day, visits)) in stats.days.iter().enumerate() {
+                let height = *visits as f64 / max as f64 * 100.0;
+                writeln!(
+                    writer,
+                    r#"<rect x="{index}" y="{:.2}" width="1" height="{height:.2}"><title>{day}: {visits}</title></rect>"#,
+                    100.0 - height
+                )?;
+            }
+            writeln!(writer, "</svg>")?;
+            writeln!(
+                writer,
+                "<p>{first} to {last}, the busiest day had {max} visits.</p>"
+            )?;
+        }
+        _ => writeln!(writer, "<p>No visits.</p>")?,
+    }
+
+    writeln!(writer, "<h2>Top domains</h2>")?;
+    if let Some((_, max)) = stats.top_hosts.first() {
+        writeln!(
+            writer,
+            r#"<svg viewBox="0 0 600 {}">"#,
+            stats.top_hosts.len() * 24
+        )?;
+        for (index, (host, visits)) in stats.top_hosts.iter().enumerate() {
+            let y = index * 24;
+            writeln!(
+                writer,
+                r#"<text x="0" y="{}" font-size="14">{}</text><rect x="250" y="{}" width="{:.2}" height="18"><title>{visits}</title></rect><text x="255" y="{}" font-size="12" fill="white">{visits}</text>"#,
+                y + 15,
+                escape_html(host),
+                y + 2,
+                *visits as f64 / *max as f64 * 350.0,
+                y + 15
+            )?;
+        }
+        writeln!(writer, "</svg>")?;
+    } else {
+        writeln!(writer, "<p>No visits.</p>")?;
+    }
+
+    writeln!(writer, "</body></html>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use url::Url;
+
+    use super::{write_html_report, ImportStats};
+    use crate::{places::Counts, visit::Visit};
+
+    #[test]
+    fn test_html_report() {
+        let visit = |url: &str, time| Visit::new(url.parse::<Url>().unwrap(), time);
+        let visits = [
+            // 2025-01-25 10:13:20 UTC
+            visit("https://www.mozilla.org/", 1737800000000000),
+            visit("https://www.mozilla.org/about/", 1737800000000000),
+            // two days later
+            visit("https://search.nixos.org/?a=<b>", 1737972800000000),
+            visit("file:///etc/hosts", 1737972800000000),
+        ];
+        let day = |day| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+        let stats = ImportStats::new(&visits, chrono_tz::UTC);
+        assert_eq!(stats.days, [(day(25), 2), (day(26), 0), (day(27), 2)]);
+        assert_eq!(
+            stats.top_hosts,
+            [
+                ("www.mozilla.org".to_string(), 2),
+                ("file".to_string(), 1),
+                ("search.nixos.org".to_string(), 1),
+            ]
+        );
+        // days follow the time zone, it's still the day before at UTC-11
+        let shifted = ImportStats::new(&visits[..1], chrono_tz::Pacific::Pago_Pago);
+        assert_eq!(shifted.days, [(day(24), 1)]);
+
+        let mut output = Vec::new();
+        let counts = Counts {
+            inserted: 3,
+            skipped: 1,
+            ..Counts::default()
+        };
+        write_html_report(&mut output, &stats, 4, counts, 0).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("<!DOCTYPE html>"));
+        assert!(output.contains("<tr><td>Inserted</td><td>3</td></tr>"));
+        assert!(output.contains("<title>2025-01-26: 0</title>"));
+        assert!(output.contains(">www.mozilla.org</text>"));
+        // nothing is loaded from elsewhere
+        assert!(!output.contains("<script") && !output.contains("<link"));
+
+        let mut output = Vec::new();
+        write_html_report(
+            &mut output,
+            &ImportStats::default(),
+            0,
+            Counts::default(),
+            0,
+        )
+        .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("<p>No visits.</p>"));
+    }
+}
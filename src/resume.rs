@@ -0,0 +1,105 @@
+//! Progress of an import, kept in a state file for `--state-file`, so an interrupted import can be resumed.
+//!
+//! Next to how many entries were committed, the state holds what decides where batches start,
+//! so a resumed import commits the same batches as one which was never interrupted.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ResumeState {
+    /// Entries to import after filtering, to notice when the input or the filters changed.
+    pub entries: usize,
+    /// Entries whose batch was committed.
+    pub processed: usize,
+    /// Entries per batch.
+    pub batch_size: usize,
+    /// Entries skipped at the start of the input, see `--entry-offset`.
+    pub entry_offset: usize,
+}
+
+impl ResumeState {
+    /// Reads the state, or returns `None` if there is no state file yet.
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        let input = match std::fs::read_to_string(path) {
+            Ok(input) => input,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Failed to read {}.", path.display()))
+            }
+        };
+        serde_json::from_str(&input)
+            .with_context(|| format!("Failed to parse {}.", path.display()))
+            .map(Some)
+    }
+
+    /// Writes the state next to `path` first and renames it over `path`,
+    /// so an interruption never leaves a half written state behind.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::to_string(self)?)
+            .and_then(|()| std::fs::rename(&temporary, path))
+            .with_context(|| format!("Failed to write {}.", path.display()))
+    }
+
+    /// Describes how `self`, the configuration of the run to resume, differs from the current one.
+    pub fn mismatches(
+        &self,
+        entries: usize,
+        batch_size: usize,
+        entry_offset: usize,
+    ) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if self.entries != entries {
+            mismatches.push(format!(
+                "the interrupted import had {} entries to import, now there are {entries}",
+                self.entries
+            ));
+        }
+        if self.batch_size != batch_size {
+            mismatches.push(format!(
+                "the interrupted import committed batches of {} entries, not {batch_size}",
+                self.batch_size
+            ));
+        }
+        if self.entry_offset != entry_offset {
+            mismatches.push(format!(
+                "the interrupted import skipped the first {} entries, not {entry_offset}",
+                self.entry_offset
+            ));
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResumeState;
+
+    #[test]
+    fn test_resume_state() {
+        let path = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-resume-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(ResumeState::load(&path).unwrap(), None);
+
+        let state = ResumeState {
+            entries: 2500,
+            processed: 2000,
+            batch_size: 1000,
+            entry_offset: 10,
+        };
+        state.save(&path).unwrap();
+        assert_eq!(ResumeState::load(&path).unwrap(), Some(state.clone()));
+        assert!(state.mismatches(2500, 1000, 10).is_empty());
+        assert_eq!(state.mismatches(2400, 500, 10).len(), 2);
+
+        std::fs::write(&path, "{").unwrap();
+        assert!(ResumeState::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
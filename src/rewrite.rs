@@ -0,0 +1,663 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use url::Url;
+
+use crate::{
+    regex::Regex,
+    visit::{Visit, VisitType},
+};
+
+/// Rewrites `http://` URLs to `https://`.
+///
+/// If `domains` isn't empty, only URLs whose host is one of the domains or a subdomain of them are rewritten.
+/// The domains are matched case-insensitively.
+/// URLs with an explicit port are left alone, as the port most likely doesn't serve https.
+pub fn upgrade_http(url: &mut Url, domains: &[String]) -> bool {
+    if url.scheme() != "http" || url.port().is_some() {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    if !domains.is_empty() && !domains.iter().any(|domain| matches_domain(host, domain)) {
+        return false;
+    }
+    url.set_scheme("https").is_ok()
+}
+
+/// Removes query parameters, so URLs which only differ in them become the same place.
+///
+/// Parameters named in `keep` stay as they are, everything else is dropped.
+/// Returns whether the URL changed.
+pub fn merge_query(url: &mut Url, keep: &[String]) -> bool {
+    let Some(query) = url.query() else {
+        return false;
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split_once('=').map_or(*pair, |(name, _)| name);
+            // compare decoded names, but keep the pairs encoded as they were
+            let name: String = url::form_urlencoded::parse(name.as_bytes())
+                .map(|(name, _)| name.into_owned())
+                .collect();
+            !name.is_empty() && keep.contains(&name)
+        })
+        .collect();
+    if kept.len() == query.split('&').count() {
+        return false;
+    }
+    let kept = kept.join("&");
+    url.set_query(if kept.is_empty() { None } else { Some(&kept) });
+    true
+}
+
+/// Whether URLs which only differ in their `#fragment` are different places.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FragmentMode {
+    /// Keep fragments, so every anchor of a page is its own place, like Firefox does.
+    #[default]
+    Keep,
+    /// Remove fragments, so all anchors of a page are the same place.
+    Strip,
+}
+
+/// Removes the fragment of the URL, including a lone `#`. Returns whether the URL changed.
+///
+/// The url_hash and rev_host of places are calculated from the URL afterwards, so they match the stripped URL.
+pub fn strip_fragment(url: &mut Url) -> bool {
+    if url.fragment().is_none() {
+        return false;
+    }
+    url.set_fragment(None);
+    true
+}
+
+/// Moves visits with the same timestamp apart by a microsecond each, so they don't get treated as duplicates.
+///
+/// Timestamps are never moved into the next second, visits which would need that keep their timestamp.
+/// Returns how many visits were moved and how many couldn't be moved.
+pub fn decollide_timestamps(visits: &mut [Visit]) -> (usize, usize) {
+    let mut used = HashSet::with_capacity(visits.len());
+    let (mut moved, mut failed) = (0, 0);
+    for visit in visits {
+        if used.insert(visit.time) {
+            continue;
+        }
+        let second = visit.time / 1_000_000;
+        let free = (visit.time + 1..)
+            .take_while(|time| time / 1_000_000 == second)
+            .find(|time| !used.contains(time));
+        match free {
+            Some(time) => {
+                visit.time = time;
+                used.insert(time);
+                moved += 1;
+            }
+            None => failed += 1,
+        }
+    }
+    (moved, failed)
+}
+
+/// Collapses visits of the same URL which are at most `window` microseconds apart into the earliest of them,
+/// for exports which record a single visit several times. Visits are compared to the last one which was kept,
+/// so a long series of close visits doesn't collapse into a single one.
+///
+/// The kept visits stay in their order. Returns how many visits were removed.
+pub fn collapse_near_duplicates(visits: &mut Vec<Visit>, window: u64) -> usize {
+    let mut by_url: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, visit) in visits.iter().enumerate() {
+        by_url.entry(visit.url.as_str()).or_default().push(index);
+    }
+    let mut keep = vec![true; visits.len()];
+    for mut indices in by_url.into_values() {
+        indices.sort_by_key(|index| visits[*index].time);
+        let mut last_kept = visits[indices[0]].time;
+        for index in &indices[1..] {
+            let time = visits[*index].time;
+            if time - last_kept <= window {
+                keep[*index] = false;
+            } else {
+                last_kept = time;
+            }
+        }
+    }
+    let before = visits.len();
+    let mut keep = keep.into_iter();
+    visits.retain(|_| keep.next().unwrap_or(true));
+    before - visits.len()
+}
+
+/// Keeps each visit with a probability of `fraction`, drawn from a random number generator seeded with `seed`,
+/// so the same seed picks the same visits of the same input. The kept visits stay in their order.
+pub fn sample(visits: &mut Vec<Visit>, fraction: f64, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    visits.retain(|_| rng.gen_bool(fraction));
+}
+
+/// Adds a visit for every page which redirected to another one, right before the visit it redirected to,
+/// so the chain is imported linked by `from_visit`, like Firefox records redirects.
+///
+/// The first page of a chain gets the visit type, transition and referrer of the visit, the pages it
+/// redirected to become [`VisitType::RedirectTemporary`] visits, because no source records whether
+/// a redirect was permanent. The added visits are a microsecond apart before the visit, so they aren't
+/// duplicates of it. Returns how many visits were added.
+pub fn expand_redirects(visits: &mut Vec<Visit>) -> usize {
+    let mut expanded = Vec::with_capacity(visits.len());
+    let mut added = 0;
+    for mut visit in visits.drain(..) {
+        let count = visit.redirects.len();
+        for (index, url) in visit.redirects.iter().enumerate() {
+            let mut source = Visit {
+                redirects: visit.redirects[..index].to_vec(),
+                session: visit.session.clone(),
+                visit_type: VisitType::RedirectTemporary,
+                ..Visit::new(
+                    url.clone(),
+                    visit.time.saturating_sub((count - index) as u64),
+                )
+            };
+            if index == 0 {
+                source.visit_type = visit.visit_type;
+                source.transition = visit.transition.clone();
+                source.referrer = visit.referrer.clone();
+            }
+            expanded.push(source);
+        }
+        if count > 0 {
+            visit.visit_type = VisitType::RedirectTemporary;
+            added += count;
+        }
+        expanded.push(visit);
+    }
+    *visits = expanded;
+    added
+}
+
+/// Characters Windows-1252 has for the bytes 0x80 to 0x9F, where Latin-1 only has control characters.
+const WINDOWS_1252: [(char, u8); 27] = [
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8A),
+    ('‹', 0x8B),
+    ('Œ', 0x8C),
+    ('Ž', 0x8E),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9A),
+    ('›', 0x9B),
+    ('œ', 0x9C),
+    ('ž', 0x9E),
+    ('Ÿ', 0x9F),
+];
+
+/// Repairs a title whose UTF-8 was decoded as Latin-1 or Windows-1252, like `CafÃ©` for `Café`,
+/// also if that happened more than once. Returns `None` if the title doesn't look like that.
+///
+/// A title is only repaired if all its characters map back to single bytes and those bytes are valid UTF-8.
+/// Multi-byte UTF-8 is very unlikely to arise from real Latin-1 text, so correct titles stay as they are,
+/// like `Café`, whose `é` alone is no valid UTF-8, or titles with characters beyond Windows-1252.
+pub fn fix_mojibake(title: &str) -> Option<String> {
+    let mut fixed: Option<String> = None;
+    // a title encoded twice needs two rounds, more is very rare
+    for _ in 0..3 {
+        let current = fixed.as_deref().unwrap_or(title);
+        if current.is_ascii() {
+            break;
+        }
+        let bytes: Option<Vec<u8>> = current
+            .chars()
+            .map(|c| match u8::try_from(c) {
+                Ok(byte) => Some(byte),
+                Err(_) => WINDOWS_1252
+                    .iter()
+                    .find(|(character, _)| *character == c)
+                    .map(|(_, byte)| *byte),
+            })
+            .collect();
+        match bytes.and_then(|bytes| String::from_utf8(bytes).ok()) {
+            Some(decoded) if decoded != current => fixed = Some(decoded),
+            _ => break,
+        }
+    }
+    fixed
+}
+
+/// Replaces all matches of `regex` in titles with `replacement`.
+#[derive(Debug, Clone)]
+pub struct TitleRewrite {
+    pub regex: Regex,
+    pub replacement: String,
+}
+
+impl TitleRewrite {
+    /// Applies all rewrites in order. Titles which end up empty are removed.
+    pub fn apply_all(rewrites: &[TitleRewrite], title: &mut Option<String>) {
+        let Some(text) = title else {
+            return;
+        };
+        for rewrite in rewrites {
+            *text = rewrite.regex.replace_all(text, &rewrite.replacement);
+        }
+        if text.is_empty() {
+            *title = None;
+        }
+    }
+}
+
+/// Which of the titles a URL has in the input its place gets.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitleSource {
+    /// The first title in the input order.
+    #[default]
+    First,
+    /// The last title in the input order.
+    Last,
+    /// The longest title.
+    Longest,
+    /// The most common title, or the first of the most common ones.
+    MostCommon,
+}
+
+/// Gives all visits of a URL the same title, picked by `source`.
+///
+/// Visits without a title are ignored when picking, but get the picked title too.
+pub fn unify_titles(visits: &mut [Visit], source: TitleSource) {
+    let mut titles: HashMap<&str, Vec<&str>> = HashMap::new();
+    for visit in visits.iter() {
+        let titles = titles.entry(visit.url.as_str()).or_default();
+        if let Some(title) = &visit.title {
+            titles.push(title);
+        }
+    }
+
+    let picked: HashMap<String, String> = titles
+        .into_iter()
+        .filter_map(|(url, titles)| {
+            let title = match source {
+                TitleSource::First => titles.first().copied(),
+                TitleSource::Last => titles.last().copied(),
+                // rev so the first of equally long titles wins, max_by_key returns the last one
+                TitleSource::Longest => titles
+                    .iter()
+                    .rev()
+                    .max_by_key(|title| title.chars().count())
+                    .copied(),
+                TitleSource::MostCommon => {
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for title in &titles {
+                        *counts.entry(title).or_default() += 1;
+                    }
+                    titles
+                        .iter()
+                        .rev()
+                        .max_by_key(|title| counts[**title])
+                        .copied()
+                }
+            }?;
+            Some((url.to_string(), title.to_string()))
+        })
+        .collect();
+
+    for visit in visits {
+        if let Some(title) = picked.get(visit.url.as_str()) {
+            visit.title = Some(title.clone());
+        }
+    }
+}
+
+fn matches_domain(host: &str, domain: &str) -> bool {
+    let Some(start) = host.len().checked_sub(domain.len()) else {
+        return false;
+    };
+    host.get(start..)
+        .is_some_and(|suffix| suffix.eq_ignore_ascii_case(domain))
+        && (start == 0 || host[..start].ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{
+        collapse_near_duplicates, decollide_timestamps, expand_redirects, fix_mojibake,
+        merge_query, sample, strip_fragment, unify_titles, upgrade_http, TitleRewrite, TitleSource,
+    };
+    use crate::visit::{Visit, VisitType};
+
+    fn upgraded(url: &str, domains: &[&str]) -> String {
+        let mut url: Url = url.parse().unwrap();
+        let domains: Vec<String> = domains.iter().map(|domain| domain.to_string()).collect();
+        upgrade_http(&mut url, &domains);
+        url.to_string()
+    }
+
+    #[test]
+    fn test_upgrade_http() {
+        assert_eq!(
+            upgraded("http://example.com/a?b#c", &[]),
+            "https://example.com/a?b#c"
+        );
+        assert_eq!(
+            upgraded("http://example.com:80/", &[]),
+            "https://example.com/"
+        );
+        assert_eq!(
+            upgraded("http://example.com:8080/", &[]),
+            "http://example.com:8080/"
+        );
+        assert_eq!(upgraded("ftp://example.com/", &[]), "ftp://example.com/");
+        assert_eq!(
+            upgraded("http://www.example.com/", &["example.com"]),
+            "https://www.example.com/"
+        );
+        assert_eq!(
+            upgraded("http://notexample.com/", &["example.com"]),
+            "http://notexample.com/"
+        );
+        assert_eq!(
+            upgraded("http://www.example.com/", &["Example.COM"]),
+            "https://www.example.com/"
+        );
+    }
+
+    #[test]
+    fn test_merge_query() {
+        let keep = ["q".to_string(), "page id".to_string()];
+        let cases = [
+            (
+                "https://example.com/",
+                &[][..],
+                "https://example.com/",
+                false,
+            ),
+            (
+                "https://example.com/?utm_source=x",
+                &[],
+                "https://example.com/",
+                true,
+            ),
+            (
+                "https://example.com/a?utm_source=x#top",
+                &[],
+                "https://example.com/a#top",
+                true,
+            ),
+            (
+                "https://example.com/search?q=a%20b&utm_source=x&sid=1",
+                &keep,
+                "https://example.com/search?q=a%20b",
+                true,
+            ),
+            (
+                "https://example.com/?page+id=3&q",
+                &keep,
+                "https://example.com/?page+id=3&q",
+                false,
+            ),
+            (
+                "https://example.com/?sid=1",
+                &keep,
+                "https://example.com/",
+                true,
+            ),
+        ];
+        for (input, keep, expected, changed) in cases {
+            let mut url: Url = input.parse().unwrap();
+            assert_eq!(merge_query(&mut url, keep), changed, "{input}");
+            assert_eq!(url.as_str(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_strip_fragment() {
+        for (input, expected, changed) in [
+            (
+                "https://doc.rust-lang.org/std/#modules",
+                "https://doc.rust-lang.org/std/",
+                true,
+            ),
+            (
+                "https://example.com/?q=a#",
+                "https://example.com/?q=a",
+                true,
+            ),
+            ("https://example.com/a", "https://example.com/a", false),
+        ] {
+            let mut url: Url = input.parse().unwrap();
+            assert_eq!(strip_fragment(&mut url), changed, "{input}");
+            assert_eq!(url.as_str(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_decollide_timestamps() {
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let mut visits: Vec<Visit> = [5, 5, 6, 5, 1_999_999, 1_999_999]
+            .into_iter()
+            .map(|time| Visit::new(url.clone(), time))
+            .collect();
+        assert_eq!(decollide_timestamps(&mut visits), (3, 1));
+        let times: Vec<u64> = visits.iter().map(|visit| visit.time).collect();
+        assert_eq!(times, [5, 6, 7, 8, 1_999_999, 1_999_999]);
+    }
+
+    #[test]
+    fn test_collapse_near_duplicates() {
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let other: Url = "https://search.nixos.org/".parse().unwrap();
+        let mut visits: Vec<Visit> = [
+            (&url, 1000),
+            (&other, 1500),
+            (&url, 900),
+            (&url, 1900),
+            (&url, 2500),
+            (&other, 1500),
+            (&url, 3600),
+        ]
+        .into_iter()
+        .map(|(url, time)| Visit::new(url.clone(), time))
+        .collect();
+        assert_eq!(collapse_near_duplicates(&mut visits, 1000), 3);
+        let visits: Vec<(&str, u64)> = visits
+            .iter()
+            .map(|visit| (visit.url.as_str(), visit.time))
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                ("https://search.nixos.org/", 1500),
+                ("https://www.mozilla.org/", 900),
+                ("https://www.mozilla.org/", 2500),
+                ("https://www.mozilla.org/", 3600),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let visits: Vec<Visit> = (0..1000)
+            .map(|time| Visit::new(url.clone(), time))
+            .collect();
+        let sampled = |fraction, seed| {
+            let mut visits = visits.clone();
+            sample(&mut visits, fraction, seed);
+            visits.iter().map(|visit| visit.time).collect::<Vec<u64>>()
+        };
+        let tenth = sampled(0.1, 0);
+        assert!((50..150).contains(&tenth.len()), "{}", tenth.len());
+        assert!(tenth.is_sorted());
+        assert_eq!(sampled(0.1, 0), tenth);
+        assert_ne!(sampled(0.1, 1), tenth);
+        assert_eq!(sampled(1.0, 0).len(), 1000);
+    }
+
+    #[test]
+    fn test_expand_redirects() {
+        let url = |url: &str| -> Url { url.parse().unwrap() };
+        let mut visits = vec![
+            Visit {
+                visit_type: VisitType::Typed,
+                referrer: Some(url("https://search.nixos.org/")),
+                redirects: vec![url("http://mozilla.org/"), url("https://mozilla.org/")],
+                ..Visit::new(url("https://www.mozilla.org/"), 1000)
+            },
+            Visit::new(url("https://search.nixos.org/"), 2000),
+        ];
+        assert_eq!(expand_redirects(&mut visits), 2);
+        let visits: Vec<_> = visits
+            .iter()
+            .map(|visit| {
+                (
+                    visit.url.as_str(),
+                    visit.time,
+                    visit.visit_type,
+                    visit.redirects.last().map(Url::as_str),
+                    visit.referrer.as_ref().map(Url::as_str),
+                )
+            })
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                (
+                    "http://mozilla.org/",
+                    998,
+                    VisitType::Typed,
+                    None,
+                    Some("https://search.nixos.org/")
+                ),
+                (
+                    "https://mozilla.org/",
+                    999,
+                    VisitType::RedirectTemporary,
+                    Some("http://mozilla.org/"),
+                    None
+                ),
+                (
+                    "https://www.mozilla.org/",
+                    1000,
+                    VisitType::RedirectTemporary,
+                    Some("https://mozilla.org/"),
+                    Some("https://search.nixos.org/")
+                ),
+                (
+                    "https://search.nixos.org/",
+                    2000,
+                    VisitType::Link,
+                    None,
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fix_mojibake() {
+        for (garbled, fixed) in [
+            ("CafÃ© â€“ Speisekarte", "Café – Speisekarte"),
+            (
+                "Ã„rger mit der BahnÂ\u{a0}â€” SÃ¼ddeutsche",
+                "Ärger mit der Bahn\u{a0}— Süddeutsche",
+            ),
+            ("â€œQuotedâ€\u{9d} â„¢", "“Quoted” ™"),
+            ("æ—¥æœ¬èªž", "日本語"),
+            ("ðŸ¦Š Firefox", "🦊 Firefox"),
+            // encoded twice
+            ("CafÃƒÂ©", "Café"),
+        ] {
+            assert_eq!(fix_mojibake(garbled).as_deref(), Some(fixed), "{garbled}");
+        }
+        // correct titles stay as they are
+        for title in [
+            "Mozilla",
+            "Café",
+            "Ärger mit der Bahn",
+            "日本語",
+            "Preis: 5 € – 10 €",
+            "CafÃ© with 日本語",
+            "",
+        ] {
+            assert_eq!(fix_mojibake(title), None, "{title}");
+        }
+    }
+
+    #[test]
+    fn test_title_rewrite() {
+        let rewrites = [
+            TitleRewrite {
+                regex: r" - Google (Search|Suche)$".parse().unwrap(),
+                replacement: String::new(),
+            },
+            TitleRewrite {
+                regex: r"^\((\d+)\) ".parse().unwrap(),
+                replacement: String::new(),
+            },
+        ];
+        let rewritten = |title: &str| {
+            let mut title = Some(title.to_string());
+            TitleRewrite::apply_all(&rewrites, &mut title);
+            title
+        };
+        assert_eq!(rewritten("rust - Google Search").as_deref(), Some("rust"));
+        assert_eq!(rewritten("(3) Inbox").as_deref(), Some("Inbox"));
+        assert_eq!(rewritten(" - Google Suche"), None);
+    }
+
+    #[test]
+    fn test_unify_titles() {
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let other: Url = "https://search.nixos.org/".parse().unwrap();
+        let titles = [
+            None,
+            Some("Mozilla"),
+            Some("Home - Mozilla"),
+            Some("Mozilla "),
+        ];
+        let mut input: Vec<Visit> = titles
+            .iter()
+            .chain(&[Some("Mozilla")])
+            .enumerate()
+            .map(|(time, title)| Visit {
+                title: title.map(str::to_string),
+                ..Visit::new(url.clone(), time as u64)
+            })
+            .collect();
+        input.push(Visit::new(other.clone(), 10));
+
+        for (source, expected) in [
+            (TitleSource::First, "Mozilla"),
+            (TitleSource::Last, "Mozilla"),
+            (TitleSource::Longest, "Home - Mozilla"),
+            (TitleSource::MostCommon, "Mozilla"),
+        ] {
+            let mut visits = input.clone();
+            unify_titles(&mut visits, source);
+            for visit in &visits[..5] {
+                assert_eq!(visit.title.as_deref(), Some(expected), "{source:?}");
+            }
+            assert_eq!(visits[5].title, None);
+        }
+
+        let mut visits = input[..4].to_vec();
+        unify_titles(&mut visits, TitleSource::Last);
+        assert_eq!(visits[0].title.as_deref(), Some("Mozilla "));
+    }
+}
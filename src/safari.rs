@@ -0,0 +1,141 @@
+//! Reads history exported from Safari.
+//!
+//! Safari 17 and later export a `History.json` from File > Export Browsing Data,
+//! with a `metadata` object and the visits in `history`, timed in microseconds since the unix epoch.
+//! Older versions only keep the history in `History.db`, whose visits are timed in Mac absolute time,
+//! seconds since 2001-01-01. Dumps of it, e.g. of
+//! `SELECT url, title, visit_time FROM history_visits JOIN history_items ON history_items.id = history_item`,
+//! can be read as an array of such rows, or as the `history` of an object.
+
+use url::Url;
+
+use crate::{time::from_mac_absolute_time, visit::Visit};
+
+/// Top-level key of Safari's own export, next to `history`.
+pub const METADATA_KEY: &str = "metadata";
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum SafariFile {
+    Export { history: Vec<SafariEntry> },
+    Rows(Vec<SafariEntry>),
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SafariEntry {
+    pub url: Url,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Microseconds since the unix epoch, as Safari exports them.
+    #[serde(default)]
+    pub time_usec: Option<u64>,
+    /// Seconds since 2001-01-01, possibly with a fraction, as `History.db` stores them.
+    #[serde(default)]
+    pub visit_time: Option<f64>,
+}
+
+/// Converts the parsed entries into visits, in the order of the input.
+pub fn into_visits(file: SafariFile) -> anyhow::Result<Vec<Visit>> {
+    let entries = match file {
+        SafariFile::Export { history } => history,
+        SafariFile::Rows(rows) => rows,
+    };
+    entries
+        .into_iter()
+        .map(|entry| {
+            let time = match (entry.time_usec, entry.visit_time) {
+                (Some(time), _) => time,
+                (None, Some(time)) => from_mac_absolute_time(time).ok_or_else(|| {
+                    anyhow::anyhow!("Visit of {} has the invalid time {time}.", entry.url)
+                })?,
+                (None, None) => anyhow::bail!("Visit of {} has no time.", entry.url),
+            };
+            Ok(Visit {
+                title: entry.title.filter(|title| !title.is_empty()),
+                ..Visit::new(entry.url, time)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::takeout::{detect, parse_history, Source};
+
+    fn visits(input: &str) -> Vec<(String, Option<String>, u64)> {
+        parse_history(input, Some(Source::Safari))
+            .unwrap()
+            .into_iter()
+            .map(|visit| (visit.url.to_string(), visit.title, visit.time))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_safari_export() {
+        let input = r#"{
+    "metadata": {
+        "browser_name": "Safari",
+        "browser_version": "17.0",
+        "data_type": "history",
+        "export_time_usec": 1737900000000000,
+        "schema_version": 1
+    },
+    "history": [
+        {
+            "url": "https://www.mozilla.org/",
+            "title": "Mozilla",
+            "time_usec": 1737800000123456,
+            "visit_count": 1,
+            "latest_visit_was_load_failure": false,
+            "latest_visit_was_http_get": true
+        },
+        { "url": "https://search.nixos.org/", "title": "", "time_usec": 1737700000000000 }
+    ]
+}"#;
+        assert_eq!(detect(input), Some(Source::Safari));
+        assert_eq!(
+            visits(input),
+            [
+                (
+                    "https://www.mozilla.org/".to_string(),
+                    Some("Mozilla".to_string()),
+                    1737800000123456
+                ),
+                (
+                    "https://search.nixos.org/".to_string(),
+                    None,
+                    1737700000000000
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_safari_database_rows() {
+        // 2025-01-25 10:13:20.123456 UTC in Mac absolute time
+        let input = r#"[
+    { "url": "https://www.mozilla.org/", "title": "Mozilla", "visit_time": 759492800.123456 },
+    { "url": "https://search.nixos.org/", "visit_time": 0 }
+]"#;
+        assert_eq!(
+            visits(input),
+            [
+                (
+                    "https://www.mozilla.org/".to_string(),
+                    Some("Mozilla".to_string()),
+                    1737800000123456
+                ),
+                (
+                    "https://search.nixos.org/".to_string(),
+                    None,
+                    978307200000000
+                ),
+            ]
+        );
+        assert!(parse_history(
+            r#"[{ "url": "https://www.mozilla.org/" }]"#,
+            Some(Source::Safari)
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Imports into a copy of a database, which replaces the original only once everything succeeded.
+///
+/// The copy lives next to the original, so it needs as much free disk space as the original
+/// and can replace it with an atomic rename. It is deleted if [`SafeWrite::finish`] isn't called.
+pub struct SafeWrite {
+    original: PathBuf,
+    copy: PathBuf,
+    finished: bool,
+}
+
+/// Checkpoints `original` so it holds everything in its WAL and copies it to `copy`.
+///
+/// The checkpoint doesn't change the content of `original`, so this also makes copies to import into
+/// instead of the original, for `--output-db`.
+pub fn copy_database(original: &Path, copy: &Path) -> anyhow::Result<()> {
+    {
+        let connection = rusqlite::Connection::open_with_flags(
+            original,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .with_context(|| format!("Failed to open {}.", original.display()))?;
+        // returns (busy, log frames, checkpointed frames)
+        let busy: u32 =
+            connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        if busy != 0 {
+            anyhow::bail!(
+                "{} is in use, close Firefox before importing.",
+                original.display()
+            );
+        }
+    }
+
+    std::fs::copy(original, copy).with_context(|| {
+        format!(
+            "Failed to copy {} to {}.",
+            original.display(),
+            copy.display()
+        )
+    })?;
+    Ok(())
+}
+
+impl SafeWrite {
+    /// Copies the original with [`copy_database`].
+    pub fn prepare(original: &Path) -> anyhow::Result<Self> {
+        let copy = with_suffix(original, ".import");
+        copy_database(original, &copy)?;
+        Ok(Self {
+            original: original.to_path_buf(),
+            copy,
+            finished: false,
+        })
+    }
+
+    /// The database to import into.
+    pub fn path(&self) -> &Path {
+        &self.copy
+    }
+
+    /// Replaces the original with the copy. All connections to the copy must be closed.
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        // move everything into the copy itself, its WAL would be left behind by the rename
+        rusqlite::Connection::open(&self.copy)?.query_row(
+            "PRAGMA wal_checkpoint(TRUNCATE)",
+            [],
+            |_| Ok(()),
+        )?;
+        // the checkpoint in prepare left them empty, but they must not be applied to the new database
+        for suffix in ["-wal", "-shm"] {
+            remove_if_exists(&with_suffix(&self.copy, suffix))?;
+            remove_if_exists(&with_suffix(&self.original, suffix))?;
+        }
+        std::fs::rename(&self.copy, &self.original).with_context(|| {
+            format!(
+                "Failed to replace {} with {}.",
+                self.original.display(),
+                self.copy.display()
+            )
+        })?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for SafeWrite {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = remove_if_exists(&with_suffix(&self.copy, suffix));
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_database, SafeWrite};
+
+    #[test]
+    fn test_safe_write() {
+        let original = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-safe-write-{}.sqlite",
+            std::process::id()
+        ));
+        let connection = rusqlite::Connection::open(&original).unwrap();
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL; CREATE TABLE t (n INTEGER); INSERT INTO t VALUES (1);",
+            )
+            .unwrap();
+        drop(connection);
+        let rows = || -> u32 {
+            rusqlite::Connection::open(&original)
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        // abandoned imports leave the original alone and clean up the copy
+        let safe_write = SafeWrite::prepare(&original).unwrap();
+        let copy = safe_write.path().to_path_buf();
+        rusqlite::Connection::open(&copy)
+            .unwrap()
+            .execute("INSERT INTO t VALUES (2)", [])
+            .unwrap();
+        drop(safe_write);
+        assert!(!copy.exists());
+        assert_eq!(rows(), 1);
+
+        let safe_write = SafeWrite::prepare(&original).unwrap();
+        rusqlite::Connection::open(safe_write.path())
+            .unwrap()
+            .execute("INSERT INTO t VALUES (2)", [])
+            .unwrap();
+        safe_write.finish().unwrap();
+        assert!(!copy.exists());
+        assert_eq!(rows(), 2);
+
+        let _ = std::fs::remove_file(&original);
+    }
+
+    #[test]
+    fn test_copy_database() {
+        let original = std::env::temp_dir().join(format!(
+            "chrome-takeout-to-firefox-copy-{}.sqlite",
+            std::process::id()
+        ));
+        let copy = original.with_extension("copy.sqlite");
+        let connection = rusqlite::Connection::open(&original).unwrap();
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL; CREATE TABLE t (n INTEGER); INSERT INTO t VALUES (1);",
+            )
+            .unwrap();
+
+        // rows which are only in the WAL of an open connection are copied too
+        copy_database(&original, &copy).unwrap();
+        let rows: u32 = rusqlite::Connection::open(&copy)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+        drop(connection);
+        for path in [&original, &copy] {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+            }
+        }
+    }
+}
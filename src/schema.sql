@@ -0,0 +1,88 @@
+-- Subset of the places schema Firefox creates, see:
+-- https://searchfox.org/mozilla-central/source/toolkit/components/places/nsPlacesTables.h
+-- https://searchfox.org/mozilla-central/source/toolkit/components/places/nsPlacesIndexes.h
+
+CREATE TABLE IF NOT EXISTS moz_origins (
+    id INTEGER PRIMARY KEY,
+    prefix TEXT NOT NULL,
+    host TEXT NOT NULL,
+    frecency INTEGER NOT NULL,
+    recalc_frecency INTEGER NOT NULL DEFAULT 0,
+    alt_frecency INTEGER,
+    recalc_alt_frecency INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (prefix, host)
+);
+
+CREATE TABLE IF NOT EXISTS moz_places (
+    id INTEGER PRIMARY KEY,
+    url LONGVARCHAR,
+    title LONGVARCHAR,
+    rev_host LONGVARCHAR,
+    visit_count INTEGER DEFAULT 0,
+    hidden INTEGER DEFAULT 0 NOT NULL,
+    typed INTEGER DEFAULT 0 NOT NULL,
+    frecency INTEGER DEFAULT -1 NOT NULL,
+    last_visit_date INTEGER,
+    guid TEXT,
+    foreign_count INTEGER DEFAULT 0 NOT NULL,
+    url_hash INTEGER DEFAULT 0 NOT NULL,
+    description TEXT,
+    preview_image_url TEXT,
+    site_name TEXT,
+    origin_id INTEGER REFERENCES moz_origins(id),
+    recalc_frecency INTEGER NOT NULL DEFAULT 0,
+    alt_frecency INTEGER,
+    recalc_alt_frecency INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS moz_places_url_hashindex ON moz_places (url_hash);
+CREATE INDEX IF NOT EXISTS moz_places_hostindex ON moz_places (rev_host);
+CREATE INDEX IF NOT EXISTS moz_places_visitcount ON moz_places (visit_count);
+CREATE INDEX IF NOT EXISTS moz_places_frecencyindex ON moz_places (frecency);
+CREATE INDEX IF NOT EXISTS moz_places_lastvisitdateindex ON moz_places (last_visit_date);
+CREATE UNIQUE INDEX IF NOT EXISTS moz_places_guid_uniqueindex ON moz_places (guid);
+CREATE INDEX IF NOT EXISTS moz_places_originidindex ON moz_places (origin_id);
+
+CREATE TABLE IF NOT EXISTS moz_historyvisits (
+    id INTEGER PRIMARY KEY,
+    from_visit INTEGER,
+    place_id INTEGER,
+    visit_date INTEGER,
+    visit_type INTEGER,
+    session INTEGER,
+    source INTEGER DEFAULT 0 NOT NULL,
+    triggeringPlaceId INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS moz_historyvisits_placedateindex ON moz_historyvisits (place_id, visit_date);
+CREATE INDEX IF NOT EXISTS moz_historyvisits_fromindex ON moz_historyvisits (from_visit);
+CREATE INDEX IF NOT EXISTS moz_historyvisits_dateindex ON moz_historyvisits (visit_date);
+
+CREATE TABLE IF NOT EXISTS moz_keywords (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    keyword TEXT UNIQUE,
+    place_id INTEGER,
+    post_data TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS moz_keywords_placepostdata_uniqueindex ON moz_keywords (place_id, post_data);
+
+CREATE TABLE IF NOT EXISTS moz_places_metadata (
+    id INTEGER PRIMARY KEY,
+    place_id INTEGER NOT NULL,
+    referrer_place_id INTEGER,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL DEFAULT 0,
+    total_view_time INTEGER NOT NULL DEFAULT 0,
+    typing_time INTEGER NOT NULL DEFAULT 0,
+    key_presses INTEGER NOT NULL DEFAULT 0,
+    scrolling_time INTEGER NOT NULL DEFAULT 0,
+    scrolling_distance INTEGER NOT NULL DEFAULT 0,
+    document_type INTEGER NOT NULL DEFAULT 0,
+    search_query_id INTEGER,
+    FOREIGN KEY (place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
+    FOREIGN KEY (referrer_place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
+    CHECK (place_id != referrer_place_id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS moz_places_metadata_placecreated_uniqueindex ON moz_places_metadata (place_id, created_at);
@@ -0,0 +1,109 @@
+//! Renames the tables the SQL of this tool uses, for forks or Firefox versions which renamed them, for `--schema-map`.
+//!
+//! The SQL is written with the table names of current Firefox versions, which are replaced
+//! according to a TOML file mapping them to the actual names:
+//!
+//! ```toml
+//! moz_historyvisits = "moz_historyvisits_v2"
+//! moz_places = "places"
+//! ```
+//!
+//! Tables which aren't listed keep their name.
+
+use std::{borrow::Cow, collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+/// Table names by the name Firefox uses, empty to use the Firefox names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaMap {
+    tables: HashMap<String, String>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl SchemaMap {
+    /// Parses a mapping in the format described in the [module documentation](self).
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let tables: HashMap<String, String> = toml::from_str(input)?;
+        // the names end up in the SQL as they are, so only plain identifiers are allowed
+        for (table, name) in &tables {
+            for name in [table, name] {
+                if !is_identifier(name) {
+                    anyhow::bail!("{name:?} is not a valid table name.");
+                }
+            }
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}.", path.display()))?;
+        Self::parse(&input).with_context(|| format!("Failed to parse {}.", path.display()))
+    }
+
+    /// The actual name of the table Firefox calls `table`.
+    pub fn table<'a>(&'a self, table: &'a str) -> &'a str {
+        self.tables.get(table).map_or(table, String::as_str)
+    }
+
+    /// Replaces the Firefox table names in `sql` with the actual ones.
+    ///
+    /// Every word which is exactly the name of a mapped table is replaced, also in string literals
+    /// like the ones looking up tables in `sqlite_master`. Longer names like `moz_places_metadata`
+    /// are left alone if only `moz_places` is mapped.
+    pub fn sql<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        if self.tables.is_empty() {
+            return Cow::Borrowed(sql);
+        }
+        let mut result = String::with_capacity(sql.len());
+        let mut rest = sql;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            let (before, word) = rest.split_at(start);
+            let end = word
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(word.len());
+            let (word, after) = word.split_at(end);
+            result.push_str(before);
+            result.push_str(self.table(word));
+            rest = after;
+        }
+        result.push_str(rest);
+        Cow::Owned(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemaMap;
+
+    #[test]
+    fn test_schema_map() {
+        let sql =
+            "SELECT COUNT(*) FROM moz_historyvisits JOIN moz_places ON moz_places.id = place_id
+            WHERE name IN ('moz_places', 'moz_places_metadata') AND title = 'moz_places!'";
+        assert_eq!(SchemaMap::default().sql(sql), sql);
+
+        let map = SchemaMap::parse(
+            r#"
+            moz_places = "places"
+            moz_historyvisits = "visits_v2"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            map.sql(sql),
+            "SELECT COUNT(*) FROM visits_v2 JOIN places ON places.id = place_id
+            WHERE name IN ('places', 'moz_places_metadata') AND title = 'places!'"
+        );
+        assert_eq!(map.table("moz_places"), "places");
+        assert_eq!(map.table("moz_origins"), "moz_origins");
+
+        assert!(SchemaMap::parse(r#"moz_places = "places; DROP TABLE x""#).is_err());
+        assert!(SchemaMap::parse(r#"moz_places = "1places""#).is_err());
+    }
+}
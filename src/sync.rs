@@ -0,0 +1,234 @@
+//! Reads history from a dump of Chrome's sync data, as saved from `chrome://sync-internals`.
+//!
+//! Sync uses Chrome's internal representation, so times are in microseconds since 1601
+//! and 64 bit numbers are usually written as strings.
+
+use url::Url;
+
+use crate::{
+    time::from_windows_epoch,
+    transition::{self, TransitionMap},
+    visit::{Visit, VisitType},
+};
+
+/// Top-level keys of a sync dump, one per data type that carries history.
+pub const KEYS: &[&str] = &["history", "typed_urls"];
+
+#[derive(serde::Deserialize)]
+pub struct ChromeSyncFile {
+    /// Visits of the `HISTORY` data type, which newer Chrome versions sync.
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// Pages of the older `TYPED_URLS` data type, each with all of its visits.
+    #[serde(default)]
+    pub typed_urls: Vec<TypedUrlEntry>,
+}
+
+/// A single visit, see `history_specifics.proto`.
+#[derive(serde::Deserialize, Debug)]
+pub struct HistoryEntry {
+    #[serde(deserialize_with = "deserialize_number")]
+    pub visit_time_windows_epoch_micros: u64,
+    /// The URLs of the redirect chain which lead to the visit, the last one is the visited page.
+    pub redirect_entries: Vec<RedirectEntry>,
+    #[serde(default)]
+    pub referrer_url: Option<Url>,
+    #[serde(default)]
+    pub page_transition: Option<PageTransitionEntry>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RedirectEntry {
+    pub url: Url,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PageTransitionEntry {
+    #[serde(default)]
+    pub core_transition: Option<Transition>,
+}
+
+/// A page with all of its visits, see `typed_url_specifics.proto`.
+#[derive(serde::Deserialize, Debug)]
+pub struct TypedUrlEntry {
+    pub url: Url,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Times of the visits in microseconds since 1601.
+    #[serde(default, deserialize_with = "deserialize_numbers")]
+    pub visits: Vec<u64>,
+    /// Transitions of the visits, in the same order.
+    #[serde(default)]
+    pub visit_transitions: Vec<Transition>,
+}
+
+/// A `ui::PageTransition`, written as its number or the name of its core type.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Transition {
+    Number(u64),
+    Text(String),
+}
+
+impl Transition {
+    /// The name of the transition, see [`transition::name`].
+    pub fn name(&self) -> String {
+        match self {
+            Self::Number(number) => transition::number_name(*number),
+            Self::Text(text) => transition::name(text),
+        }
+    }
+}
+
+fn deserialize_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Number(u64),
+        Text(String),
+    }
+    match serde::Deserialize::deserialize(deserializer)? {
+        Number::Number(number) => Ok(number),
+        Number::Text(text) => text.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+fn deserialize_numbers<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u64>, D::Error> {
+    #[derive(serde::Deserialize)]
+    struct Number(#[serde(deserialize_with = "deserialize_number")] u64);
+    let numbers: Vec<Number> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(numbers.into_iter().map(|Number(number)| number).collect())
+}
+
+fn convert_time(time: u64, url: &Url) -> anyhow::Result<u64> {
+    from_windows_epoch(time)
+        .ok_or_else(|| anyhow::anyhow!("Visit of {url} at {time} is before 1970."))
+}
+
+/// Converts a parsed sync dump into visits, the `history` ones first.
+pub fn into_visits(file: ChromeSyncFile) -> anyhow::Result<Vec<Visit>> {
+    let transitions = TransitionMap::default();
+    let mut visits = Vec::new();
+    for entry in file.history {
+        let mut redirect_entries = entry.redirect_entries;
+        let Some(page) = redirect_entries.pop() else {
+            continue;
+        };
+        let transition = entry
+            .page_transition
+            .and_then(|transition| transition.core_transition)
+            .map(|transition| transition.name());
+        visits.push(Visit {
+            title: page.title.filter(|title| !title.is_empty()),
+            visit_type: transition.as_deref().map_or(VisitType::Link, |transition| {
+                transitions.visit_type(transition)
+            }),
+            transition,
+            referrer: entry.referrer_url,
+            redirects: redirect_entries
+                .into_iter()
+                .map(|redirect| redirect.url)
+                .collect(),
+            ..Visit::new(
+                page.url.clone(),
+                convert_time(entry.visit_time_windows_epoch_micros, &page.url)?,
+            )
+        });
+    }
+    for entry in file.typed_urls {
+        let title = entry.title.filter(|title| !title.is_empty());
+        for (index, time) in entry.visits.iter().enumerate() {
+            let transition = entry.visit_transitions.get(index).map(Transition::name);
+            visits.push(Visit {
+                title: title.clone(),
+                visit_type: transition
+                    .as_deref()
+                    .map_or(VisitType::Typed, |transition| {
+                        transitions.visit_type(transition)
+                    }),
+                transition,
+                ..Visit::new(entry.url.clone(), convert_time(*time, &entry.url)?)
+            });
+        }
+    }
+    Ok(visits)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        takeout::{read, Source},
+        visit::VisitType,
+    };
+
+    #[test]
+    fn test_read_sync() {
+        let input = r#"{
+    "history": [
+        {
+            "visit_time_windows_epoch_micros": "13382273600123456",
+            "redirect_entries": [
+                { "url": "http://mozilla.org/" },
+                { "url": "https://www.mozilla.org/", "title": "Mozilla" }
+            ],
+            "page_transition": { "core_transition": "TYPED" }
+        }
+    ],
+    "typed_urls": [
+        {
+            "url": "https://search.nixos.org/",
+            "title": "NixOS Search",
+            "visits": ["13382273600000000", 13382273700000000],
+            "visit_transitions": ["0", 268435464]
+        }
+    ]
+}"#;
+        let visits = read(input.as_bytes(), None).unwrap();
+        let visits: Vec<(&str, Option<&str>, u64, VisitType)> = visits
+            .iter()
+            .map(|visit| {
+                (
+                    visit.url.as_str(),
+                    visit.title.as_deref(),
+                    visit.time,
+                    visit.visit_type,
+                )
+            })
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                (
+                    "https://www.mozilla.org/",
+                    Some("Mozilla"),
+                    1737800000123456,
+                    VisitType::Typed
+                ),
+                (
+                    "https://search.nixos.org/",
+                    Some("NixOS Search"),
+                    1737800000000000,
+                    VisitType::Link
+                ),
+                (
+                    "https://search.nixos.org/",
+                    Some("NixOS Search"),
+                    1737800100000000,
+                    VisitType::Reload
+                ),
+            ]
+        );
+
+        let visits = read(input.as_bytes(), None).unwrap();
+        let redirects: Vec<&str> = visits[0].redirects.iter().map(|url| url.as_str()).collect();
+        assert_eq!(redirects, ["http://mozilla.org/"]);
+        assert!(visits[1].redirects.is_empty());
+
+        // an explicit source skips the detection
+        assert!(read(input.as_bytes(), Some(Source::Takeout)).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{collections::HashMap, io::Read};
+
+use serde::de::IgnoredAny;
+use url::Url;
+
+use crate::{
+    firefox_json, safari, sync,
+    transition::{self, TransitionMap},
+    visit::{Visit, VisitType},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ChromeTakeoutFile {
+    #[serde(rename = "Browser History")]
+    pub history: Box<[ChromeTakeoutEntry]>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ChromeTakeoutEntry {
+    pub title: String,
+    pub url: Url,
+    pub time_usec: u64,
+    /// The page which linked to this one.
+    /// Chrome doesn't export this, but other sources might.
+    #[serde(default)]
+    pub referrer: Option<Url>,
+    /// Address bar keyword of the page.
+    /// Chrome doesn't export this either.
+    #[serde(default)]
+    pub keyword: Option<String>,
+    /// Browsing session of the visit, a string or a number.
+    /// Chrome doesn't export this either.
+    #[serde(default, deserialize_with = "deserialize_session")]
+    pub session: Option<String>,
+    /// How long the page was open in microseconds.
+    /// Chrome doesn't export this either.
+    #[serde(default)]
+    pub duration_usec: Option<u64>,
+    /// How the page was opened, like `LINK` or `TYPED`.
+    #[serde(default)]
+    pub page_transition: Option<String>,
+}
+
+fn deserialize_session<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Session {
+        Number(u64),
+        Text(String),
+    }
+    let session: Option<Session> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(session.map(|session| match session {
+        Session::Number(number) => number.to_string(),
+        Session::Text(text) => text,
+    }))
+}
+
+impl From<ChromeTakeoutEntry> for Visit {
+    fn from(entry: ChromeTakeoutEntry) -> Self {
+        let transition = entry.page_transition.as_deref().map(transition::name);
+        Self {
+            title: if entry.title.is_empty() {
+                None
+            } else {
+                Some(entry.title)
+            },
+            referrer: entry.referrer,
+            keyword: entry.keyword,
+            session: entry.session,
+            duration: entry.duration_usec,
+            visit_type: transition.as_deref().map_or(VisitType::Link, |transition| {
+                TransitionMap::default().visit_type(transition)
+            }),
+            transition,
+            ..Visit::new(entry.url, entry.time_usec)
+        }
+    }
+}
+
+/// JSON layouts the history can be read from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The `History.json` of a chrome takeout.
+    Takeout,
+    /// A dump of Chrome's sync data, see [`crate::sync`].
+    ChromeSync,
+    /// History exported from Firefox by an add-on, see [`crate::firefox_json`].
+    FirefoxJson,
+    /// History exported from Safari, or dumped from its `History.db`, see [`crate::safari`].
+    Safari,
+}
+
+/// Guesses the layout of the input by its top-level keys, or an array for Firefox exports.
+/// Dumps of Safari's `History.db` are arrays too, they need an explicit [`Source::Safari`].
+///
+/// Returns `None` if the input isn't JSON or has none of the expected keys.
+pub fn detect(input: &str) -> Option<Source> {
+    if serde_json::from_str::<Vec<IgnoredAny>>(input).is_ok() {
+        return Some(Source::FirefoxJson);
+    }
+    let keys: HashMap<String, IgnoredAny> = serde_json::from_str(input).ok()?;
+    if keys.contains_key("Browser History") {
+        Some(Source::Takeout)
+    } else if keys.contains_key(safari::METADATA_KEY) && keys.contains_key("history") {
+        Some(Source::Safari)
+    } else if sync::KEYS.iter().any(|key| keys.contains_key(*key)) {
+        Some(Source::ChromeSync)
+    } else {
+        None
+    }
+}
+
+/// Reads the history from a chrome takeout `History.json`.
+///
+/// Syntax errors include a snippet of the input around the error.
+pub fn read_history(reader: impl Read) -> anyhow::Result<Vec<Visit>> {
+    read(reader, Some(Source::Takeout))
+}
+
+/// Reads the history in the layout of `source`, or the detected one if it is `None`.
+/// Input which can't be detected is read as a takeout, to get a helpful error.
+pub fn read(mut reader: impl Read, source: Option<Source>) -> anyhow::Result<Vec<Visit>> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    parse_history(&input, source)
+}
+
+/// Parses history like [`read`] from a string.
+pub fn parse_history(input: &str, source: Option<Source>) -> anyhow::Result<Vec<Visit>> {
+    match source.or_else(|| detect(input)).unwrap_or(Source::Takeout) {
+        Source::Takeout => {
+            let takeout: ChromeTakeoutFile = parse(input)?;
+            Ok(takeout
+                .history
+                .into_vec()
+                .into_iter()
+                .map(Visit::from)
+                .collect())
+        }
+        Source::ChromeSync => sync::into_visits(parse(input)?),
+        Source::FirefoxJson => firefox_json::into_visits(parse(input)?),
+        Source::Safari => safari::into_visits(parse(input)?),
+    }
+}
+
+/// Parses JSON, adding a snippet of the input around syntax errors.
+fn parse<T: serde::de::DeserializeOwned>(input: &str) -> anyhow::Result<T> {
+    serde_json::from_str(input).map_err(|error| {
+        match snippet(input, error.line(), error.column()) {
+            Some(snippet) => anyhow::anyhow!("{error}\n\n{snippet}"),
+            None => error.into(),
+        }
+    })
+}
+
+/// How many characters to show on each side of an error.
+const SNIPPET_RADIUS: usize = 40;
+
+/// Returns the part of `line` around `column` with a caret pointing at it,
+/// as reported by [`serde_json::Error`].
+fn snippet(input: &str, line: usize, column: usize) -> Option<String> {
+    let text = input.lines().nth(line.checked_sub(1)?)?;
+    // serde_json counts columns in bytes
+    let position = text
+        .char_indices()
+        .take_while(|(index, _)| *index < column.saturating_sub(1))
+        .count();
+    let chars: Vec<char> = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let start = position.saturating_sub(SNIPPET_RADIUS);
+    let end = (position + SNIPPET_RADIUS).min(chars.len());
+
+    let prefix = if start > 0 { "..." } else { "" };
+    let suffix = if end < chars.len() { "..." } else { "" };
+    let excerpt: String = chars[start..end].iter().collect();
+    let caret = " ".repeat(prefix.len() + position - start);
+    Some(format!("{prefix}{excerpt}{suffix}\n{caret}^"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_history;
+    use crate::{
+        transition::TransitionMap,
+        visit::{Visit, VisitType},
+    };
+
+    #[test]
+    fn test_error_snippet() {
+        let input = r#"{
+    "Browser History": [
+        { "title": "Mozilla", "url": "https://www.mozilla.org/", "time_usec": 1737800000123456, },
+    ]
+}"#;
+        let error = read_history(input.as_bytes()).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            r#"trailing comma at line 3 column 97
+
+...a.org/", "time_usec": 1737800000123456, },
+                                           ^"#
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let input = r#"{
+    "Browser History": [
+        { "title": "", "url": "https://a.com/", "time_usec": 1, "session": 12 },
+        { "title": "", "url": "https://b.com/", "time_usec": 2, "session": "abc" },
+        { "title": "", "url": "https://c.com/", "time_usec": 3 }
+    ]
+}"#;
+        let sessions: Vec<Option<String>> = read_history(input.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|visit| visit.session)
+            .collect();
+        assert_eq!(
+            sessions,
+            [Some("12".to_string()), Some("abc".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn test_page_transition() {
+        let input = r#"{
+    "Browser History": [
+        { "title": "", "url": "https://a.com/", "time_usec": 1, "page_transition": "TYPED" },
+        { "title": "", "url": "https://b.com/", "time_usec": 2, "page_transition": "FORM_SUBMIT" },
+        { "title": "", "url": "https://c.com/", "time_usec": 3 }
+    ]
+}"#;
+        let mut visits = read_history(input.as_bytes()).unwrap();
+        let visit_types = |visits: &[Visit]| -> Vec<VisitType> {
+            visits.iter().map(|visit| visit.visit_type).collect()
+        };
+        assert_eq!(
+            visit_types(&visits),
+            [VisitType::Typed, VisitType::Link, VisitType::Link]
+        );
+        assert_eq!(visits[0].transition.as_deref(), Some("TYPED"));
+        assert_eq!(visits[2].transition, None);
+
+        TransitionMap::parse("default = 4\n[transitions]\nTYPED = 1")
+            .unwrap()
+            .apply(&mut visits);
+        assert_eq!(
+            visit_types(&visits),
+            [VisitType::Link, VisitType::Embed, VisitType::Link]
+        );
+    }
+}
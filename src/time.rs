@@ -0,0 +1,252 @@
+//! Conversions between the microsecond timestamps Firefox stores and human readable times.
+
+use std::time::Duration;
+
+const MICROSECONDS_PER_SECOND: u64 = 1_000_000;
+
+/// Microseconds between the Windows epoch, 1601-01-01, and the unix epoch.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600 * MICROSECONDS_PER_SECOND;
+
+/// Converts microseconds since the Windows epoch, which Chrome uses internally, into microseconds since the unix epoch.
+pub fn from_windows_epoch(time: u64) -> Option<u64> {
+    time.checked_sub(WINDOWS_EPOCH_OFFSET)
+}
+
+/// Microseconds between the unix epoch and the Mac epoch, 2001-01-01.
+const MAC_EPOCH_OFFSET: i64 = 978_307_200 * MICROSECONDS_PER_SECOND as i64;
+
+/// Converts Mac absolute time, seconds since 2001-01-01 which Safari uses, into microseconds since the unix epoch.
+pub fn from_mac_absolute_time(seconds: f64) -> Option<u64> {
+    if !seconds.is_finite() {
+        return None;
+    }
+    let micros = (seconds * MICROSECONDS_PER_SECOND as f64).round() as i64;
+    u64::try_from(micros.checked_add(MAC_EPOCH_OFFSET)?).ok()
+}
+
+/// Parses an RFC 3339 timestamp into microseconds since the unix epoch.
+pub fn parse_rfc3339(time: &str) -> Option<u64> {
+    let (date, time) = time.split_once(['T', 't'])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset_seconds) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let split = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(split);
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let seconds =
+            i64::from(hours.parse::<u32>().ok()? * 3600 + minutes.parse::<u32>().ok()? * 60);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        (time, sign * seconds)
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<u32>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    // only microseconds are kept, like Firefox stores them
+    let fraction: String = fraction.chars().chain("000000".chars()).take(6).collect();
+    let micros: u64 = fraction.parse().ok()?;
+
+    let seconds = days_from_civil(year, month, day) * 86_400
+        + i64::from(hour * 3600 + minute * 60 + second)
+        - offset_seconds;
+    Some(u64::try_from(seconds).ok()? * MICROSECONDS_PER_SECOND + micros)
+}
+
+/// Days since the unix epoch of a date in the proleptic Gregorian calendar.
+///
+/// See: https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = i64::from((153 * ((month + 9) % 12) + 2) / 5 + day - 1);
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses a duration with a unit, e.g. `500ms`, `2s`, `1.5m` or `1h`.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let split = duration
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .ok_or_else(|| format!("{duration:?} has no unit, like ms, s, m or h."))?;
+    let (number, unit) = duration.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("{number:?} isn't a number."))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("Unknown unit {unit:?}, use ms, s, m or h.")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
+}
+
+/// Parses a duration with a sign, e.g. `+2h` or `-30m`, into microseconds, see [`parse_duration`] for the units.
+/// Durations without a sign are positive.
+pub fn parse_shift(shift: &str) -> Result<i64, String> {
+    let (sign, duration) = match shift.strip_prefix('-') {
+        Some(duration) => (-1, duration),
+        None => (1, shift.strip_prefix('+').unwrap_or(shift)),
+    };
+    let micros = i64::try_from(parse_duration(duration)?.as_micros())
+        .map_err(|_| format!("{shift:?} is too long."))?;
+    Ok(sign * micros)
+}
+
+/// Moves microseconds since the unix epoch by `shift` microseconds, stopping at the epoch.
+pub fn shift(time: u64, shift: i64) -> u64 {
+    time.saturating_add_signed(shift)
+}
+
+/// Formats microseconds since the unix epoch as e.g. `2025-01-25 10:13:20 UTC`.
+pub fn format_utc(time: u64) -> String {
+    let seconds = time / MICROSECONDS_PER_SECOND;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Formats microseconds since the unix epoch in the time zone `tz`, as e.g. `2025-01-25 05:13:20 EST`.
+pub fn format_in(time: u64, tz: chrono_tz::Tz) -> String {
+    let seconds = (time / MICROSECONDS_PER_SECOND) as i64;
+    match chrono::DateTime::from_timestamp(seconds, 0) {
+        Some(time) => time
+            .with_timezone(&tz)
+            .format("%Y-%m-%d %H:%M:%S %Z")
+            .to_string(),
+        // past the year 262143, which no visit will reach
+        None => format_utc(time),
+    }
+}
+
+/// Date in the proleptic Gregorian calendar of days since the unix epoch.
+///
+/// See: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        format_in, format_utc, from_mac_absolute_time, parse_duration, parse_rfc3339, parse_shift,
+        shift,
+    };
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339("2025-01-25T10:13:20.123456Z"),
+            Some(1737800000123456)
+        );
+        assert_eq!(
+            parse_rfc3339("2025-01-25T10:13:20.1Z"),
+            Some(1737800000100000)
+        );
+        assert_eq!(
+            parse_rfc3339("2025-01-25T11:13:20+01:00"),
+            Some(1737800000000000)
+        );
+        assert_eq!(
+            parse_rfc3339("2025-01-25T05:43:20-04:30"),
+            Some(1737800000000000)
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29T00:00:00Z"),
+            Some(1709164800000000)
+        );
+        assert_eq!(parse_rfc3339("2025-13-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2025-01-25 10:13:20"), None);
+    }
+
+    #[test]
+    fn test_from_mac_absolute_time() {
+        assert_eq!(from_mac_absolute_time(0.0), Some(978307200000000));
+        assert_eq!(
+            from_mac_absolute_time(759492800.123456),
+            parse_rfc3339("2025-01-25T10:13:20.123456Z")
+        );
+        // before 2001, but after the unix epoch
+        assert_eq!(from_mac_absolute_time(-978307200.0), Some(0));
+        assert_eq!(from_mac_absolute_time(-978307201.0), None);
+        assert_eq!(from_mac_absolute_time(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(1737800000123456), "2025-01-25 10:13:20 UTC");
+        assert_eq!(format_utc(1709164800000000), "2024-02-29 00:00:00 UTC");
+        for time in [951782400000000, 1737800000123456, 4102444799000000] {
+            let formatted = format_utc(time);
+            let rfc3339 = formatted.replace(' ', "T").replace("TUTC", "Z");
+            assert_eq!(parse_rfc3339(&rfc3339), Some(time / 1_000_000 * 1_000_000));
+            assert_eq!(format_in(time, chrono_tz::UTC), formatted);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("2").is_err());
+        assert!(parse_duration("2 s").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("2d").is_err());
+    }
+
+    #[test]
+    fn test_shift() {
+        assert_eq!(parse_shift("+2h"), Ok(7_200_000_000));
+        assert_eq!(parse_shift("90s"), Ok(90_000_000));
+        assert_eq!(parse_shift("-30m"), Ok(-1_800_000_000));
+        assert_eq!(parse_shift("-1.5s"), Ok(-1_500_000));
+        assert!(parse_shift("+-2h").is_err());
+        assert!(parse_shift("-").is_err());
+
+        let time = 1737800000123456;
+        assert_eq!(shift(time, parse_shift("+1h").unwrap()), 1737803600123456);
+        assert_eq!(shift(time, parse_shift("-1h").unwrap()), 1737796400123456);
+        assert_eq!(shift(1000, -2000), 0);
+    }
+
+    #[test]
+    fn test_format_in() {
+        let tz = "America/New_York".parse().unwrap();
+        assert_eq!(format_in(1737800000123456, tz), "2025-01-25 05:13:20 EST");
+        assert_eq!(format_in(1751400000000000, tz), "2025-07-01 16:00:00 EDT");
+        let tz = "Asia/Kolkata".parse().unwrap();
+        assert_eq!(format_in(1737800000123456, tz), "2025-01-25 15:43:20 IST");
+    }
+}
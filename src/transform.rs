@@ -0,0 +1,138 @@
+//! Rewrites entries with a user supplied [rhai](https://rhai.rs) script.
+//!
+//! The script runs once per entry, with the entry in the variable `entry`, an object with the fields
+//!
+//! - `url`: the URL as a string
+//! - `title`: the title, or an empty string if there is none
+//! - `time`: microseconds since the unix epoch
+//! - `skip`: `false`, set it to `true` to drop the entry
+//!
+//! Changes to the fields are written back to the entry, e.g.
+//!
+//! ```rhai
+//! if entry.url.contains("/ads/") {
+//!     entry.skip = true;
+//! }
+//! entry.title.replace(" - YouTube", "");
+//! ```
+
+use std::path::Path;
+
+use anyhow::Context;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use crate::visit::Visit;
+
+pub struct Transform {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Transform {
+    pub fn new(script: &str) -> anyhow::Result<Self> {
+        let engine = Engine::new();
+        // rhai's errors aren't Send, so they are turned into strings
+        let ast = engine
+            .compile(script)
+            .map_err(|error| anyhow::anyhow!("{error}"))?;
+        Ok(Self { engine, ast })
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let script = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}.", path.display()))?;
+        Self::new(&script).with_context(|| format!("Failed to compile {}.", path.display()))
+    }
+
+    /// Runs the script on `visit`, returning whether the visit should be kept.
+    pub fn apply(&self, visit: &mut Visit) -> anyhow::Result<bool> {
+        let mut entry = Map::new();
+        entry.insert("url".into(), visit.url.to_string().into());
+        entry.insert(
+            "title".into(),
+            visit.title.clone().unwrap_or_default().into(),
+        );
+        entry.insert("time".into(), Dynamic::from_int(visit.time as i64));
+        entry.insert("skip".into(), false.into());
+
+        let mut scope = Scope::new();
+        scope.push("entry", entry);
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|error| anyhow::anyhow!("{error}"))?;
+        let entry: Map = scope
+            .get_value("entry")
+            .context("The script replaced entry with something which isn't an object.")?;
+
+        let field = |name: &str| {
+            entry
+                .get(name)
+                .cloned()
+                .with_context(|| format!("The script removed entry.{name}."))
+        };
+        if field("skip")?
+            .as_bool()
+            .map_err(|kind| anyhow::anyhow!("entry.skip must be a bool, got {kind}."))?
+        {
+            return Ok(false);
+        }
+        let url = field("url")?
+            .into_string()
+            .map_err(|kind| anyhow::anyhow!("entry.url must be a string, got {kind}."))?;
+        if url != visit.url.as_str() {
+            visit.url = url
+                .parse()
+                .with_context(|| format!("The script returned the invalid url {url:?}."))?;
+            visit.url_hash = None;
+        }
+        let title = field("title")?
+            .into_string()
+            .map_err(|kind| anyhow::anyhow!("entry.title must be a string, got {kind}."))?;
+        visit.title = if title.is_empty() { None } else { Some(title) };
+        let time = field("time")?
+            .as_int()
+            .map_err(|kind| anyhow::anyhow!("entry.time must be an integer, got {kind}."))?;
+        visit.time = u64::try_from(time)
+            .with_context(|| format!("The script returned the negative time {time}."))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::Transform;
+    use crate::visit::Visit;
+
+    #[test]
+    fn test_transform() {
+        let transform = Transform::new(
+            r#"
+            if entry.url.contains("/ads/") {
+                entry.skip = true;
+            }
+            entry.url.replace("http://", "https://");
+            entry.title.replace(" - YouTube", "");
+            entry.time += 1;
+            "#,
+        )
+        .unwrap();
+        let url: Url = "http://www.youtube.com/watch?v=1".parse().unwrap();
+        let mut visit = Visit {
+            title: Some("Video - YouTube".to_string()),
+            ..Visit::new(url, 1000)
+        };
+        assert!(transform.apply(&mut visit).unwrap());
+        assert_eq!(visit.url.as_str(), "https://www.youtube.com/watch?v=1");
+        assert_eq!(visit.title.as_deref(), Some("Video"));
+        assert_eq!(visit.time, 1001);
+
+        let url: Url = "https://example.com/ads/1".parse().unwrap();
+        assert!(!transform.apply(&mut Visit::new(url, 1000)).unwrap());
+
+        let transform = Transform::new(r#"entry.url = "not a url";"#).unwrap();
+        let url: Url = "https://example.com/".parse().unwrap();
+        assert!(transform.apply(&mut Visit::new(url, 1000)).is_err());
+    }
+}
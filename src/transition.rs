@@ -0,0 +1,189 @@
+//! Maps Chrome's page transitions to Firefox visit types.
+//!
+//! The built-in mapping can be overridden with a TOML file, for `--transition-map`:
+//!
+//! ```toml
+//! # visit type of transitions which aren't listed, 1 (link) if it is missing
+//! default = 1
+//!
+//! [transitions]
+//! FORM_SUBMIT = 2
+//! AUTO_TOPLEVEL = 1
+//! ```
+//!
+//! Transitions are matched case-insensitively. Numeric transitions are matched by the name of their core type.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+use crate::visit::{Visit, VisitType};
+
+/// Names of Chrome's core transitions, indexed by their number.
+///
+/// See: https://source.chromium.org/chromium/chromium/src/+/main:ui/base/page_transition_types.h
+pub const CORE_TRANSITIONS: &[&str] = &[
+    "LINK",
+    "TYPED",
+    "AUTO_BOOKMARK",
+    "AUTO_SUBFRAME",
+    "MANUAL_SUBFRAME",
+    "GENERATED",
+    "AUTO_TOPLEVEL",
+    "FORM_SUBMIT",
+    "RELOAD",
+    "KEYWORD",
+    "KEYWORD_GENERATED",
+];
+
+/// Normalizes a transition to the name it is looked up by.
+///
+/// Numbers become the name of their core type, qualifiers in the upper bits are ignored.
+/// Unknown numbers and names are kept as they are, so they can be mapped too.
+pub fn name(transition: &str) -> String {
+    if !transition.is_empty() && transition.bytes().all(|c| c.is_ascii_digit()) {
+        if let Ok(number) = transition.parse::<u64>() {
+            return number_name(number);
+        }
+    }
+    transition.to_ascii_uppercase()
+}
+
+/// Like [`name`] for a numeric transition.
+pub fn number_name(number: u64) -> String {
+    let core = number & 0xff;
+    match CORE_TRANSITIONS.get(core as usize) {
+        Some(name) => name.to_string(),
+        None => core.to_string(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionMapFile {
+    #[serde(default)]
+    default: Option<u32>,
+    #[serde(default)]
+    transitions: HashMap<String, u32>,
+}
+
+/// Visit types of transitions, with a fallback for the ones it doesn't know.
+#[derive(Debug, Clone)]
+pub struct TransitionMap {
+    transitions: HashMap<String, VisitType>,
+    default: VisitType,
+}
+
+impl Default for TransitionMap {
+    fn default() -> Self {
+        let transitions = [
+            ("TYPED", VisitType::Typed),
+            ("AUTO_BOOKMARK", VisitType::Bookmark),
+            ("AUTO_SUBFRAME", VisitType::FramedLink),
+            ("MANUAL_SUBFRAME", VisitType::FramedLink),
+            ("GENERATED", VisitType::Typed),
+            ("RELOAD", VisitType::Reload),
+            ("KEYWORD", VisitType::Typed),
+            ("KEYWORD_GENERATED", VisitType::Typed),
+        ];
+        Self {
+            transitions: transitions
+                .into_iter()
+                .map(|(name, visit_type)| (name.to_string(), visit_type))
+                .collect(),
+            default: VisitType::Link,
+        }
+    }
+}
+
+impl TransitionMap {
+    /// Parses a mapping in the format described in the [module documentation](self),
+    /// on top of the built-in one.
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let file: TransitionMapFile = toml::from_str(input)?;
+        let visit_type = |number: u32| {
+            VisitType::from_u32(number)
+                .with_context(|| format!("{number} is not a Firefox visit type."))
+        };
+        let mut map = Self::default();
+        if let Some(default) = file.default {
+            map.default = visit_type(default)?;
+        }
+        for (transition, number) in file.transitions {
+            let visit_type =
+                visit_type(number).with_context(|| format!("Invalid mapping of {transition}."))?;
+            map.transitions.insert(name(&transition), visit_type);
+        }
+        Ok(map)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}.", path.display()))?;
+        Self::parse(&input).with_context(|| format!("Failed to parse {}.", path.display()))
+    }
+
+    /// Returns the visit type of a transition, which is normalized with [`name`] first.
+    pub fn visit_type(&self, transition: &str) -> VisitType {
+        self.transitions
+            .get(&name(transition))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Sets the visit type of visits with a transition, for `--transition-map`.
+    ///
+    /// Visits without one keep theirs, they come from inputs which don't record transitions.
+    pub fn apply(&self, visits: &mut [Visit]) {
+        for visit in visits {
+            if let Some(transition) = &visit.transition {
+                visit.visit_type = self.visit_type(transition);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransitionMap;
+    use crate::visit::VisitType;
+
+    #[test]
+    fn test_default_transition_map() {
+        let map = TransitionMap::default();
+        assert_eq!(map.visit_type("typed"), VisitType::Typed);
+        assert_eq!(map.visit_type("LINK"), VisitType::Link);
+        // RELOAD with the CHAIN_END qualifier
+        assert_eq!(map.visit_type("268435464"), VisitType::Reload);
+        assert_eq!(map.visit_type("FORM_SUBMIT"), VisitType::Link);
+        assert_eq!(map.visit_type("something else"), VisitType::Link);
+    }
+
+    #[test]
+    fn test_transition_map() {
+        let map = TransitionMap::parse(
+            r#"
+            default = 4
+
+            [transitions]
+            form_submit = 2
+            "6" = 5
+            CUSTOM = 7
+            "#,
+        )
+        .unwrap();
+        assert_eq!(map.visit_type("FORM_SUBMIT"), VisitType::Typed);
+        assert_eq!(
+            map.visit_type("AUTO_TOPLEVEL"),
+            VisitType::RedirectPermanent
+        );
+        assert_eq!(map.visit_type("custom"), VisitType::Download);
+        // the built-in mapping is kept
+        assert_eq!(map.visit_type("TYPED"), VisitType::Typed);
+        assert_eq!(map.visit_type("LINK"), VisitType::Embed);
+
+        assert!(TransitionMap::parse("default = 0").is_err());
+        assert!(TransitionMap::parse("[transitions]\nTYPED = 10").is_err());
+        assert!(TransitionMap::parse("typed = 2").is_err());
+    }
+}
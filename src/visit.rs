@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use url::Url;
+
+use crate::{
+    hash,
+    health::{self, Problem},
+};
+
+/// Longest title Firefox stores, longer ones are truncated. See `TITLE_LENGTH_MAX` in `nsNavHistory.h`.
+pub const MAX_TITLE_LENGTH: usize = 4096;
+
+/// Earliest plausible visit time, 1990-01-01, before the web existed.
+/// Earlier times are usually a missing time stored as 0.
+const MIN_TIME: u64 = 631_152_000_000_000;
+
+/// How far in the future a visit time may be, to allow for skewed clocks.
+const MAX_TIME_AHEAD: u64 = 24 * 60 * 60 * 1_000_000;
+
+/// A single visit to a page, independent of the format it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Visit {
+    pub url: Url,
+    pub title: Option<String>,
+    /// Time of the visit in microseconds since the unix epoch.
+    pub time: u64,
+    pub visit_type: VisitType,
+    /// The page which linked to this one.
+    pub referrer: Option<Url>,
+    /// Pages which redirected to this one, in order, so the last one redirected to this page.
+    /// Only sources which record redirects have any, see [`crate::rewrite::expand_redirects`].
+    /// If there are any, the visit is linked to the last one's instead of the referrer's.
+    pub redirects: Vec<Url>,
+    /// Keyword to open the page with from the address bar.
+    pub keyword: Option<String>,
+    /// Identifier of the browsing session the visit belongs to, in whatever form the source uses.
+    pub session: Option<String>,
+    /// How long the page was open in microseconds.
+    pub duration: Option<u64>,
+    /// Chrome's page transition of the visit, normalized with [`crate::transition::name`].
+    /// `visit_type` is already derived from it, this keeps it to map it differently.
+    pub transition: Option<String>,
+    /// `moz_places.url_hash` of `url`, if the caller already computed it, e.g. in parallel.
+    /// It isn't checked, so it has to be cleared when `url` changes.
+    pub url_hash: Option<u64>,
+}
+
+impl Visit {
+    pub fn new(url: Url, time: u64) -> Self {
+        Self {
+            url,
+            title: None,
+            time,
+            visit_type: VisitType::default(),
+            referrer: None,
+            redirects: Vec::new(),
+            keyword: None,
+            session: None,
+            duration: None,
+            transition: None,
+            url_hash: None,
+        }
+    }
+
+    /// Checks whether the visit can be imported, and truncates its title to [`MAX_TITLE_LENGTH`] like Firefox would.
+    ///
+    /// URLs with an opaque origin but a host are left to the import, which counts them separately.
+    pub fn validate(mut self) -> Result<Self, EntryError> {
+        match health::check(&self.url) {
+            Some(Problem::UnsupportedScheme) => {
+                return Err(EntryError::UnsupportedScheme(self.url.scheme().to_string()))
+            }
+            Some(Problem::TooLong) => return Err(EntryError::UrlTooLong(self.url.as_str().len())),
+            Some(Problem::NoHost) if self.url.scheme() == "file" => {
+                return Err(EntryError::FileUrl)
+            }
+            Some(Problem::NoHost) => return Err(EntryError::NoHost),
+            Some(Problem::OpaqueOrigin) | None => {}
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_micros() as u64);
+        if self.time < MIN_TIME || self.time > now + MAX_TIME_AHEAD {
+            return Err(EntryError::ImplausibleTime(self.time));
+        }
+        if let Some(title) = &mut self.title {
+            if let Some((index, _)) = title.char_indices().nth(MAX_TITLE_LENGTH) {
+                title.truncate(index);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns the precomputed `url_hash`, or computes it.
+    pub fn url_hash(&self) -> anyhow::Result<u64> {
+        match self.url_hash {
+            Some(url_hash) => Ok(url_hash),
+            None => hash::hash(self.url.as_str()),
+        }
+    }
+}
+
+/// Why [`Visit::validate`] rejected an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryError {
+    /// Firefox never adds URLs with this scheme to its history, like `about:` or `javascript:`.
+    UnsupportedScheme(String),
+    /// The URL is longer than [`health::MAX_URL_LENGTH`], which Firefox doesn't store.
+    UrlTooLong(usize),
+    /// The URL has no host, which every place needs for its origin.
+    NoHost,
+    /// A `file:` URL, which Firefox stores in its history but which isn't imported, as it has no host.
+    FileUrl,
+    /// The time, in microseconds since the unix epoch, is before the web existed or in the future.
+    ImplausibleTime(u64),
+}
+
+impl EntryError {
+    /// Short description shared by all errors of the same kind, for summaries.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::UnsupportedScheme(_) => "an unsupported scheme",
+            Self::UrlTooLong(_) => "a URL which is too long",
+            Self::NoHost => "a URL without a host",
+            Self::FileUrl => "a file: URL",
+            Self::ImplausibleTime(_) => "an implausible time",
+        }
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedScheme(scheme) => {
+                write!(f, "Firefox doesn't store {scheme}: URLs in its history.")
+            }
+            Self::UrlTooLong(length) => write!(
+                f,
+                "The URL is {length} bytes long, Firefox only stores up to {}.",
+                health::MAX_URL_LENGTH
+            ),
+            Self::NoHost => write!(f, "The URL has no host."),
+            Self::FileUrl => write!(
+                f,
+                "Firefox stores file: URLs in its history, but they aren't imported."
+            ),
+            Self::ImplausibleTime(time) => write!(
+                f,
+                "The time {} is implausible for a visit.",
+                crate::time::format_utc(*time)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {}
+
+/// How the user got to a page, stored as `moz_historyvisits.visit_type`.
+///
+/// See: https://searchfox.org/mozilla-central/source/toolkit/components/places/nsINavHistoryService.idl
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum VisitType {
+    #[default]
+    Link = 1,
+    Typed = 2,
+    Bookmark = 3,
+    Embed = 4,
+    RedirectPermanent = 5,
+    RedirectTemporary = 6,
+    Download = 7,
+    FramedLink = 8,
+    Reload = 9,
+}
+
+impl VisitType {
+    pub fn as_u32(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_u32(number: u32) -> Option<Self> {
+        Some(match number {
+            1 => Self::Link,
+            2 => Self::Typed,
+            3 => Self::Bookmark,
+            4 => Self::Embed,
+            5 => Self::RedirectPermanent,
+            6 => Self::RedirectTemporary,
+            7 => Self::Download,
+            8 => Self::FramedLink,
+            9 => Self::Reload,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{EntryError, Visit, MAX_TITLE_LENGTH};
+
+    /// 2025-01-25 10:13:20 UTC
+    const TIME: u64 = 1737800000000000;
+
+    fn validate(url: &str, time: u64) -> Result<Visit, EntryError> {
+        let url: Url = url.parse().unwrap();
+        Visit::new(url, time).validate()
+    }
+
+    #[test]
+    fn test_validate() {
+        let visit = validate("https://www.mozilla.org/", TIME).unwrap();
+        assert_eq!(visit.url.as_str(), "https://www.mozilla.org/");
+        // left to the import, which counts them
+        assert!(validate("foo://bar/", TIME).is_ok());
+    }
+
+    #[test]
+    fn test_validate_unsupported_scheme() {
+        assert_eq!(
+            validate("about:config", TIME).unwrap_err(),
+            EntryError::UnsupportedScheme("about".to_string())
+        );
+        assert_eq!(
+            validate("data:text/plain,hi", TIME).unwrap_err(),
+            EntryError::UnsupportedScheme("data".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_url_too_long() {
+        let url = format!("https://example.com/{}", "a".repeat(70000));
+        assert_eq!(
+            validate(&url, TIME).unwrap_err(),
+            EntryError::UrlTooLong(url.len())
+        );
+    }
+
+    #[test]
+    fn test_validate_no_host() {
+        assert_eq!(
+            validate("mailto:someone@example.com", TIME).unwrap_err(),
+            EntryError::NoHost
+        );
+        assert_eq!(
+            validate("file:///home/user/index.html", TIME).unwrap_err(),
+            EntryError::FileUrl
+        );
+    }
+
+    #[test]
+    fn test_validate_implausible_time() {
+        assert_eq!(
+            validate("https://www.mozilla.org/", 0).unwrap_err(),
+            EntryError::ImplausibleTime(0)
+        );
+        // milliseconds mistaken for microseconds
+        assert_eq!(
+            validate("https://www.mozilla.org/", TIME * 1000).unwrap_err(),
+            EntryError::ImplausibleTime(TIME * 1000)
+        );
+    }
+
+    #[test]
+    fn test_validate_truncates_titles() {
+        let url: Url = "https://www.mozilla.org/".parse().unwrap();
+        let visit = Visit {
+            title: Some("ä".repeat(MAX_TITLE_LENGTH + 10)),
+            ..Visit::new(url, TIME)
+        };
+        let title = visit.validate().unwrap().title.unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_LENGTH);
+    }
+}
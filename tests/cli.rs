@@ -0,0 +1,108 @@
+//! Runs the command line and checks its exit codes, which scripts rely on.
+
+mod common;
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use chrome_takeout_to_firefox::places::FirefoxHistoryBuilder;
+use common::TempDb;
+
+const TAKEOUT: &str = r#"{
+    "Browser History": [
+        { "title": "Mozilla", "url": "https://www.mozilla.org/", "time_usec": 1737800000000000 },
+        { "title": "Local", "url": "http://localhost:8080/", "time_usec": 1737700000000000 }
+    ]
+}"#;
+
+/// Imports `input` from stdin into `db` and returns the exit code.
+fn import(input: &str, db: &Path, args: &[&str]) -> i32 {
+    run(input, db, args).status.code().unwrap()
+}
+
+fn run(input: &str, db: &Path, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chrome-takeout-to-firefox"))
+        .arg("-")
+        .arg(db)
+        .args(["--init-schema", "--format", "none"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // invalid arguments exit before the input is read, which breaks the pipe
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_success() {
+    let db = TempDb::new("cli-success");
+    assert_eq!(import(TAKEOUT, &db.path, &[]), 0);
+
+    // nothing to import is a success too
+    let db = TempDb::new("cli-empty");
+    assert_eq!(import(r#"{ "Browser History": [] }"#, &db.path, &[]), 0);
+    let db = TempDb::new("cli-filtered");
+    assert_eq!(
+        import(
+            TAKEOUT,
+            &db.path,
+            &["--url-filter", "^https://example\\.com/"]
+        ),
+        0
+    );
+}
+
+#[test]
+fn test_error() {
+    let db = TempDb::new("cli-error");
+    assert_eq!(import("not json", &db.path, &[]), 1);
+}
+
+#[test]
+fn test_invalid_arguments() {
+    let db = TempDb::new("cli-arguments");
+    assert_eq!(import(TAKEOUT, &db.path, &["--no-such-flag"]), 2);
+}
+
+#[test]
+fn test_nothing_inserted() {
+    let db = TempDb::new("cli-nothing-inserted");
+    assert_eq!(import(TAKEOUT, &db.path, &[]), 0);
+    assert_eq!(import(TAKEOUT, &db.path, &[]), 3);
+}
+
+#[test]
+fn test_partial_failure() {
+    let db = TempDb::new("cli-partial-failure");
+    drop(
+        FirefoxHistoryBuilder::new()
+            .create_schema(true)
+            .open(&db.path)
+            .unwrap(),
+    );
+    let connection = rusqlite::Connection::open(&db.path).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TRIGGER fail_localhost BEFORE INSERT ON moz_places WHEN NEW.url LIKE 'http://localhost%'
+            BEGIN SELECT RAISE(ABORT, 'no localhost'); END;",
+        )
+        .unwrap();
+    drop(connection);
+    assert_eq!(import(TAKEOUT, &db.path, &[]), 4);
+}
+
+#[test]
+fn test_duplicate_policy_count() {
+    let db = TempDb::new("cli-count");
+    assert_eq!(import(TAKEOUT, &db.path, &[]), 0);
+    let output = run(TAKEOUT, &db.path, &["--duplicate-policy", "count"]);
+    // the counted visits raise visit_count above the visits, which isn't a mismatch
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("--repair"), "{stderr}");
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the integration tests.
+
+use std::path::PathBuf;
+
+/// Path of a database in the temporary directory, which is removed with its journal files when dropped,
+/// so a failing assertion doesn't leave it behind.
+pub struct TempDb {
+    pub path: PathBuf,
+}
+
+impl TempDb {
+    /// A path unique to `name` and this process, nothing is created yet.
+    pub fn new(name: &str) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!(
+                "chrome-takeout-to-firefox-{name}-{}.sqlite",
+                std::process::id()
+            )),
+        }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", self.path.display()));
+        }
+    }
+}
@@ -0,0 +1,24 @@
+-- A snapshot of the rows this tool stores when importing History.json, reduced to the columns the import is compared by.
+-- It is not a dump of a Firefox profile. The url_hash values were checked against Firefox's hash function,
+-- and the rest follows the places schema. Update it by hand when the import intentionally stores something else.
+-- Ids are in insertion order, guids and frecencies differ between databases and are left out.
+CREATE TABLE moz_origins (id INTEGER PRIMARY KEY, prefix TEXT NOT NULL, host TEXT NOT NULL);
+INSERT INTO moz_origins VALUES(1,'https://','www.mozilla.org');
+INSERT INTO moz_origins VALUES(2,'https://','search.nixos.org');
+-- non default ports are kept in the origin host, as Firefox does
+INSERT INTO moz_origins VALUES(3,'http://','localhost:8080');
+
+CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url LONGVARCHAR, title LONGVARCHAR, rev_host LONGVARCHAR,
+    visit_count INTEGER DEFAULT 0, last_visit_date INTEGER, url_hash INTEGER DEFAULT 0 NOT NULL, origin_id INTEGER);
+INSERT INTO moz_places VALUES(1,'https://www.mozilla.org/about/','About Mozilla','gro.allizom.www.',1,1737800000000000,47357608426557,1);
+-- the title of the newest visit, which the empty title of the older one doesn't replace
+INSERT INTO moz_places VALUES(2,'https://search.nixos.org/','NixOS Search','gro.soxin.hcraes.',2,1737700000000000,47360563686504,2);
+-- the port isn't part of rev_host
+INSERT INTO moz_places VALUES(3,'http://localhost:8080/','Local development server','tsohlacol.',1,1737500000000000,125508787546920,3);
+
+CREATE TABLE moz_historyvisits (id INTEGER PRIMARY KEY, place_id INTEGER, visit_date INTEGER, visit_type INTEGER);
+-- visit_type 1 is a link, 2 was typed
+INSERT INTO moz_historyvisits VALUES(1,1,1737800000000000,1);
+INSERT INTO moz_historyvisits VALUES(2,2,1737700000000000,2);
+INSERT INTO moz_historyvisits VALUES(3,2,1737600000000000,1);
+INSERT INTO moz_historyvisits VALUES(4,3,1737500000000000,1);
@@ -0,0 +1,69 @@
+//! Imports a takeout and checks the rows against a snapshot of a previous import,
+//! `tests/fixtures/places.golden.sql`, so changes to what is stored show up in review.
+
+mod common;
+
+use std::fs::File;
+
+use chrome_takeout_to_firefox::{places::FirefoxHistoryBuilder, takeout};
+use common::TempDb;
+use rusqlite::{types::Value, Connection};
+
+/// The compared columns, the same for the imported database and the snapshot.
+const QUERIES: &[&str] = &[
+    "SELECT id, prefix, host FROM moz_origins ORDER BY id",
+    r#"
+    SELECT id, url, title, rev_host, visit_count, last_visit_date, url_hash, origin_id
+    FROM moz_places ORDER BY id
+    "#,
+    "SELECT id, place_id, visit_date, visit_type FROM moz_historyvisits ORDER BY id",
+];
+
+fn rows(connection: &Connection, query: &str) -> Vec<Vec<Value>> {
+    let mut statement = connection.prepare(query).unwrap();
+    let columns = statement.column_count();
+    statement
+        .query_map([], |row| (0..columns).map(|index| row.get(index)).collect())
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_import_matches_snapshot() {
+    let visits = takeout::read_history(File::open("tests/fixtures/History.json").unwrap()).unwrap();
+    assert_eq!(visits.len(), 4);
+
+    let db = TempDb::new("import");
+    let mut history = FirefoxHistoryBuilder::new()
+        .create_schema(true)
+        .open(&db.path)
+        .unwrap();
+    let mut batch = history.begin().unwrap();
+    for visit in &visits {
+        batch.insert_visit(visit).unwrap();
+    }
+    batch.commit().unwrap();
+    drop(history);
+
+    let expected = Connection::open_in_memory().unwrap();
+    expected
+        .execute_batch(&std::fs::read_to_string("tests/fixtures/places.golden.sql").unwrap())
+        .unwrap();
+    let connection = Connection::open(&db.path).unwrap();
+    for query in QUERIES {
+        assert_eq!(rows(&connection, query), rows(&expected, query), "{query}");
+    }
+
+    let mut statement = connection.prepare("SELECT guid FROM moz_places").unwrap();
+    let guids = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .unwrap();
+    for guid in guids {
+        let guid = guid.unwrap();
+        assert_eq!(guid.len(), 12);
+        assert!(guid
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+    }
+}